@@ -1,9 +1,17 @@
-use criterion::{criterion_group, criterion_main, Criterion, ParameterizedBenchmark, Throughput};
-use nano_arena::{Arena, Idx};
+// The parameterized benchmark API is deprecated in criterion 0.3, but still
+// does everything these benches need.
+#![allow(deprecated)]
 
+use criterion::{
+    criterion_group, criterion_main, BatchSize, Criterion, ParameterizedBenchmark, Throughput,
+};
+use nano_arena::{Arena, HandlePool, Idx};
+
+#[allow(dead_code)]
 #[derive(Default)]
 struct Small(usize);
 
+#[allow(dead_code)]
 #[derive(Default)]
 struct Big([usize; 32]);
 
@@ -27,6 +35,104 @@ fn insert_and_delete<T: Default>(n: usize) {
     }
 }
 
+fn remove_each<T: Default>(n: usize) {
+    let mut arena = Arena::<T>::new();
+    let handles = (0..n)
+        .map(|_| arena.insert(Default::default()))
+        .collect::<Vec<_>>();
+    for idx in handles {
+        criterion::black_box(arena.remove(idx));
+    }
+}
+
+fn remove_each_fast<T: Default>(n: usize) {
+    let mut arena = Arena::<T>::new();
+    let handles = (0..n)
+        .map(|_| arena.insert(Default::default()))
+        .collect::<Vec<_>>();
+    for idx in handles {
+        criterion::black_box(arena.remove_fast(idx));
+    }
+}
+
+fn refill_after_clear<T: Default>(n: usize) {
+    let mut arena = Arena::<T>::new();
+    for _ in 0..10 {
+        for _ in 0..n {
+            arena.insert(Default::default());
+        }
+        arena.clear();
+    }
+}
+
+fn refill_after_clear_reuse<T: Default>(n: usize) {
+    let mut arena = Arena::<T>::new();
+    for _ in 0..10 {
+        for _ in 0..n {
+            arena.insert(Default::default());
+        }
+        arena.clear_reuse();
+    }
+}
+
+fn truncate<T: Default>(n: usize, len: usize) {
+    let mut arena = Arena::<T>::new();
+    for _ in 0..n {
+        arena.insert(Default::default());
+    }
+    arena.truncate(len);
+    criterion::black_box(arena);
+}
+
+// How `truncate` used to work, removing from the back one at a time
+fn truncate_by_removing<T: Default>(n: usize, len: usize) {
+    let mut arena = Arena::<T>::new();
+    for _ in 0..n {
+        arena.insert(Default::default());
+    }
+    while arena.len() > len {
+        let last = arena.get_idx_at_index(arena.len() - 1).unwrap();
+        arena.remove(last);
+    }
+    criterion::black_box(arena);
+}
+
+fn sum_entries(arena: &Arena<Small>) -> usize {
+    arena
+        .entries()
+        .map(|(idx, value)| idx.value().unwrap() + value.0)
+        .sum()
+}
+
+fn sum_entries_ref(arena: &Arena<Small>) -> usize {
+    arena
+        .entries_ref()
+        .map(|(idx, value)| idx.value().unwrap() + value.0)
+        .sum()
+}
+
+fn sum_raw_entries(arena: &Arena<Small>) -> usize {
+    arena
+        .raw_entries()
+        .map(|(index, value)| index + value.0)
+        .sum()
+}
+
+fn alloc_and_free_zst_arena(n: usize) {
+    let mut arena = Arena::<()>::new();
+    let handles: Vec<Idx> = (0..n).map(|_| arena.alloc(())).collect();
+    for idx in handles {
+        arena.swap_remove(idx);
+    }
+}
+
+fn alloc_and_free_handle_pool(pool: &mut HandlePool, n: usize) {
+    let handles: Vec<Idx> = (0..n).map(|_| pool.alloc()).collect();
+    for idx in handles {
+        pool.free(idx);
+    }
+}
+
 fn lookup<T>(arena: &Arena<T>, idx: &Idx, n: usize) {
     for _ in 0..n {
         criterion::black_box(&arena.get(idx).unwrap());
@@ -60,6 +166,136 @@ fn criterion_benchmark(c: &mut Criterion) {
         .throughput(|n| Throughput::Elements(*n as u64)),
     );
 
+    // Only the inserts are measured, not setting up the arena
+    c.bench(
+        "insert-reserved",
+        ParameterizedBenchmark::new(
+            "insert-with-capacity-small",
+            |b, n| {
+                b.iter_batched(
+                    || Arena::<Small>::with_capacity(*n),
+                    |mut arena| {
+                        for _ in 0..*n {
+                            criterion::black_box(arena.insert(Default::default()));
+                        }
+                        arena
+                    },
+                    BatchSize::SmallInput,
+                )
+            },
+            (1..3).map(|n| n * 100).collect::<Vec<usize>>(),
+        )
+        .with_function("insert-with-full-capacity-small", |b, n| {
+            b.iter_batched(
+                || Arena::<Small>::with_full_capacity(*n),
+                |mut arena| {
+                    for _ in 0..*n {
+                        criterion::black_box(arena.insert(Default::default()));
+                    }
+                    arena
+                },
+                BatchSize::SmallInput,
+            )
+        })
+        .throughput(|n| Throughput::Elements(*n as u64)),
+    );
+
+    c.bench(
+        "insert-and-delete",
+        ParameterizedBenchmark::new(
+            "insert-and-delete-small",
+            |b, n| b.iter(|| insert_and_delete::<Small>(*n)),
+            (1..3).map(|n| n * 100).collect::<Vec<usize>>(),
+        )
+        .throughput(|n| Throughput::Elements(*n as u64)),
+    );
+
+    c.bench(
+        "insert-and-delete",
+        ParameterizedBenchmark::new(
+            "insert-and-delete-big",
+            |b, n| b.iter(|| insert_and_delete::<Big>(*n)),
+            (1..3).map(|n| n * 100).collect::<Vec<usize>>(),
+        )
+        .throughput(|n| Throughput::Elements(*n as u64)),
+    );
+
+    c.bench(
+        "remove",
+        ParameterizedBenchmark::new(
+            "remove-small",
+            |b, n| b.iter(|| remove_each::<Small>(*n)),
+            (1..3).map(|n| n * 1000).collect::<Vec<usize>>(),
+        )
+        .with_function("remove-fast-small", |b, n| {
+            b.iter(|| remove_each_fast::<Small>(*n))
+        })
+        .throughput(|n| Throughput::Elements(*n as u64)),
+    );
+
+    c.bench(
+        "clear",
+        ParameterizedBenchmark::new(
+            "clear-small",
+            |b, n| b.iter(|| refill_after_clear::<Small>(*n)),
+            (1..3).map(|n| n * 100).collect::<Vec<usize>>(),
+        )
+        .with_function("clear-reuse-small", |b, n| {
+            b.iter(|| refill_after_clear_reuse::<Small>(*n))
+        })
+        .throughput(|n| Throughput::Elements(*n as u64 * 10)),
+    );
+
+    c.bench(
+        "truncate",
+        ParameterizedBenchmark::new(
+            "truncate-small",
+            |b, n| b.iter(|| truncate::<Small>(*n, 10)),
+            vec![10_000],
+        )
+        .with_function("truncate-by-removing-small", |b, n| {
+            b.iter(|| truncate_by_removing::<Small>(*n, 10))
+        })
+        .throughput(|n| Throughput::Elements(*n as u64)),
+    );
+
+    c.bench(
+        "entries",
+        ParameterizedBenchmark::new(
+            "entries-small",
+            |b, n| {
+                let arena = Arena::from_fn(*n, Small);
+                b.iter(|| criterion::black_box(sum_entries(&arena)))
+            },
+            (1..3).map(|n| n * 1000).collect::<Vec<usize>>(),
+        )
+        .with_function("entries-ref-small", |b, n| {
+            let arena = Arena::from_fn(*n, Small);
+            b.iter(|| criterion::black_box(sum_entries_ref(&arena)))
+        })
+        .with_function("raw-entries-small", |b, n| {
+            let arena = Arena::from_fn(*n, Small);
+            b.iter(|| criterion::black_box(sum_raw_entries(&arena)))
+        })
+        .throughput(|n| Throughput::Elements(*n as u64)),
+    );
+
+    // Allocating and freeing handles without values. The pool is reused between iterations, so
+    // after the first one every handle comes from the pool.
+    c.bench(
+        "zst",
+        ParameterizedBenchmark::new(
+            "alloc-and-free-zst-arena",
+            |b, n| b.iter(|| alloc_and_free_zst_arena(*n)),
+            vec![100_000],
+        )
+        .with_function("alloc-and-free-handle-pool", |b, n| {
+            let mut pool = HandlePool::new();
+            b.iter(|| alloc_and_free_handle_pool(&mut pool, *n))
+        })
+        .throughput(|n| Throughput::Elements(*n as u64)),
+    );
+
     c.bench(
         "lookup",
         ParameterizedBenchmark::new(
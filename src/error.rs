@@ -0,0 +1,57 @@
+use std::fmt;
+
+/// Why an ordering passed to [`Arena::try_apply_ordering`](crate::Arena::try_apply_ordering)
+/// was rejected. Positions refer to the offending entry in the ordering.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OrderingError {
+    WrongLength { expected: usize, found: usize },
+    Removed(usize),
+    ForeignArena(usize),
+    Duplicate(usize),
+}
+
+impl fmt::Display for OrderingError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderingError::WrongLength { expected, found } => write!(
+                formatter,
+                "ordering has {} handles but the arena has {} values",
+                found, expected
+            ),
+            OrderingError::Removed(position) => {
+                write!(formatter, "handle at {} has been removed", position)
+            }
+            OrderingError::ForeignArena(position) => {
+                write!(formatter, "handle at {} belongs to another arena", position)
+            }
+            OrderingError::Duplicate(position) => {
+                write!(formatter, "handle at {} appears more than once", position)
+            }
+        }
+    }
+}
+
+impl std::error::Error for OrderingError {}
+
+/// Why a handle couldn't be looked up in an arena, see
+/// [`Arena::try_get`](crate::Arena::try_get).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArenaError {
+    Removed,
+    ForeignArena,
+    OutOfBounds,
+}
+
+impl fmt::Display for ArenaError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArenaError::Removed => formatter.write_str("handle has been removed"),
+            ArenaError::ForeignArena => formatter.write_str("handle belongs to another arena"),
+            ArenaError::OutOfBounds => {
+                formatter.write_str("handle points past the end of the arena")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ArenaError {}
@@ -1,32 +1,57 @@
+#[cfg(loom)]
+use loom::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::borrow::Borrow;
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::iter::FromIterator;
-use std::sync::{
-    atomic::{AtomicBool, AtomicUsize, Ordering},
-    Arc,
-};
-
+#[cfg(not(loom))]
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+
+mod error;
+mod operation;
+mod pool;
+mod secondary;
 mod split;
 
-use split::ArenaSplit;
+pub use error::{ArenaError, OrderingError};
+pub use operation::Operation;
+pub use pool::HandlePool;
+pub use secondary::SecondaryMap;
+
+use split::{ArenaSplit, ArenaSplitMany};
 
+// The arena is only ever mutated through `&mut Arena`, so stores to `index` and `removed` all
+// come from one thread at a time. Handles can still be read from other threads while that
+// happens, which is why stores use `Release` and loads use `Acquire`: a reader that sees a
+// handle as removed also sees every store the arena made before removing it. A reader racing
+// with a mutation gets either the old or the new position, never a torn one, but it can be out
+// of date as soon as it's returned.
 struct IdxInner {
     index: AtomicUsize,
     removed: AtomicBool,
+    // Assigned when the value is allocated and never changed, unlike `index`. Atomic only so
+    // that recycled inners can be given a new one.
+    stable_id: AtomicU64,
+    // The id of the arena this handle belongs to. Only changes when a value is moved into
+    // another arena along with its handle.
+    arena_id: AtomicU64,
 }
 
 impl IdxInner {
     fn index(&self) -> Option<usize> {
-        let removed = self.removed.load(Ordering::Relaxed);
+        let removed = self.removed.load(Ordering::Acquire);
         if !removed {
-            Some(self.index.load(Ordering::Relaxed))
+            Some(self.index.load(Ordering::Acquire))
         } else {
             None
         }
     }
 }
 
+// Transparent so the arena can lend out its own `Arc`s as `&Idx`, see `Idx::from_inner`
 #[derive(Clone)]
+#[repr(transparent)]
 pub struct Idx {
     inner: Arc<IdxInner>,
 }
@@ -34,21 +59,76 @@ pub struct Idx {
 impl std::fmt::Debug for Idx {
     fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
         formatter.write_str(&format!(
-            "{}Idx ( {} )",
-            if self.inner.removed.load(Ordering::Relaxed) {
+            "{}Idx ( arena = {}, {} )",
+            if self.inner.removed.load(Ordering::Acquire) {
                 "Removed "
             } else {
                 ""
             },
-            self.inner.index.load(Ordering::Relaxed)
+            self.inner.arena_id.load(Ordering::Relaxed),
+            self.inner.index.load(Ordering::Acquire)
         ))
     }
 }
 
 impl Idx {
+    fn from_inner(inner: &Arc<IdxInner>) -> &Idx {
+        // Safety: `Idx` is a transparent wrapper around `Arc<IdxInner>`
+        unsafe { &*(inner as *const Arc<IdxInner> as *const Idx) }
+    }
+
     pub fn value(&self) -> Option<usize> {
         self.inner.index()
     }
+
+    pub fn is_removed(&self) -> bool {
+        self.inner.removed.load(Ordering::Acquire)
+    }
+
+    pub fn is_live(&self) -> bool {
+        !self.is_removed()
+    }
+
+    /// An id that stays the same however the arena is reordered, for referring to a value from
+    /// outside the program. Ids are unique within an arena, and are always below `u64::MAX`.
+    /// Returns `None` once removed.
+    pub fn stable_id(&self) -> Option<u64> {
+        self.value()
+            .map(|_| self.inner.stable_id.load(Ordering::Relaxed))
+    }
+
+    /// The id of the arena this handle belongs to, see [`Arena::id`].
+    pub fn arena_id(&self) -> u64 {
+        self.inner.arena_id.load(Ordering::Relaxed)
+    }
+
+    pub fn downgrade(&self) -> WeakIdx {
+        WeakIdx {
+            inner: Arc::downgrade(&self.inner),
+        }
+    }
+}
+
+/// A handle that doesn't keep the `Idx` metadata alive, for back-references that would otherwise
+/// form a cycle.
+#[derive(Clone)]
+pub struct WeakIdx {
+    inner: Weak<IdxInner>,
+}
+
+impl WeakIdx {
+    pub fn upgrade(&self) -> Option<Idx> {
+        self.inner.upgrade().map(|inner| Idx { inner })
+    }
+}
+
+impl std::fmt::Debug for WeakIdx {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self.upgrade() {
+            Some(idx) => formatter.write_str(&format!("Weak{:?}", idx)),
+            None => formatter.write_str("WeakIdx ( Dropped )"),
+        }
+    }
 }
 
 impl Eq for Idx {}
@@ -64,33 +144,74 @@ impl Hash for Idx {
     }
 }
 
+/// Orders an `Idx` by its current position in the arena, with removed handles sorting last.
+///
+/// `Idx` itself only compares by identity, so this is kept as a separate type to avoid an `Ord`
+/// that disagrees with `Eq`. Two `OrderedIdx`s are only equal if they wrap the same handle. The
+/// order follows the arena, so a sorted collection of these must be rebuilt after reordering.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct OrderedIdx(pub Idx);
+
+impl OrderedIdx {
+    fn key(&self) -> (bool, usize, *const IdxInner) {
+        let value = self.0.value();
+        (
+            value.is_none(),
+            value.unwrap_or(0),
+            Arc::as_ptr(&self.0.inner),
+        )
+    }
+}
+
+impl PartialOrd for OrderedIdx {
+    fn partial_cmp(&self, rhs: &OrderedIdx) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(rhs))
+    }
+}
+
+impl Ord for OrderedIdx {
+    fn cmp(&self, rhs: &OrderedIdx) -> std::cmp::Ordering {
+        self.key().cmp(&rhs.key())
+    }
+}
+
 const DEFAULT_CAPACITY: usize = 4;
 
 pub struct Arena<T> {
-    values: Vec<(Arc<IdxInner>, T)>,
+    // Unique to each arena, and copied into every handle it creates.
+    id: u64,
+    // Handles and values are stored separately so the values can be handed out as plain slices.
+    // Both vectors are always the same length, and `handles[i].index` is always `i`.
+    handles: Vec<Arc<IdxInner>>,
+    values: Vec<T>,
+    // Removed inners, kept around so `alloc` can reuse them instead of allocating a new `Arc`.
+    // An inner is only reused once nothing else holds a reference to it, so a recycled inner can
+    // never bring an old handle back to life.
+    pool: Vec<Arc<IdxInner>>,
+    max_capacity: Option<usize>,
+    next_stable_id: u64,
+    reindex_observer: Option<ReindexObserver>,
+    // Handles passed to `mark_remove`, waiting for `flush_removals`
+    pending_removals: Vec<Idx>,
 }
 
+/// Called with a handle and its new position whenever a value moves, see
+/// [`Arena::set_reindex_observer`].
+///
+/// The observer is stored in the arena, so it has to be `Send + Sync` for the arena to stay
+/// `Send` and `Sync`. Share state with it through an `Arc<Mutex<_>>` rather than an
+/// `Rc<RefCell<_>>`.
+pub type ReindexObserver = Box<dyn FnMut(&Idx, usize) + Send + Sync>;
+
 impl<T> Default for Arena<T> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-#[inline]
-fn choose_second_member_of_tuple_mut<A, B>((_, value): &mut (A, B)) -> &mut B {
-    value
-}
-
-#[inline]
-fn choose_second_member_of_tuple_ref<A, B>((_, value): &(A, B)) -> &B {
-    value
-}
-
+/// Iterator over mutable references to the values, returned by [`Arena::iter_mut`].
 pub struct IterMut<'a, T> {
-    iterator: std::iter::Map<
-        std::slice::IterMut<'a, (Arc<IdxInner>, T)>,
-        &'a dyn Fn(&mut (Arc<IdxInner>, T)) -> &mut T,
-    >,
+    iterator: std::slice::IterMut<'a, T>,
 }
 
 impl<'a, T> Iterator for IterMut<'a, T> {
@@ -100,11 +221,31 @@ impl<'a, T> Iterator for IterMut<'a, T> {
     }
 }
 
+/// Iterator over the values in storage order, returned by [`Arena::iter`].
+///
+/// The iterator types can be named, so they can be passed around and stored:
+///
+/// ```
+/// use nano_arena::{Arena, Iter};
+///
+/// fn total(values: Iter<'_, u32>) -> u32 {
+///     values.sum()
+/// }
+///
+/// let arena: Arena<u32> = vec![1, 2, 3].into();
+/// assert_eq!(total(arena.iter()), 6);
+/// ```
 pub struct Iter<'a, T> {
-    iterator: std::iter::Map<
-        std::slice::Iter<'a, (Arc<IdxInner>, T)>,
-        &'a dyn Fn(&(Arc<IdxInner>, T)) -> &T,
-    >,
+    iterator: std::slice::Iter<'a, T>,
+}
+
+// Not derived, since that would require `T: Clone`
+impl<T> Clone for Iter<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            iterator: self.iterator.clone(),
+        }
+    }
 }
 
 impl<'a, T> Iterator for Iter<'a, T> {
@@ -114,12 +255,57 @@ impl<'a, T> Iterator for Iter<'a, T> {
     }
 }
 
+/// Iterator over handles and mutable references to their values, returned by
+/// [`Arena::entries_mut`].
 pub struct EntriesMut<'a, T> {
-    iterator: std::slice::IterMut<'a, (Arc<IdxInner>, T)>,
+    iterator: std::iter::Zip<std::slice::Iter<'a, Arc<IdxInner>>, std::slice::IterMut<'a, T>>,
+}
+
+/// Iterator over borrowed handles and their values, returned by [`Arena::entries_ref`].
+pub struct EntriesRef<'a, T> {
+    iterator: std::iter::Zip<std::slice::Iter<'a, Arc<IdxInner>>, std::slice::Iter<'a, T>>,
+}
+
+impl<T> Clone for EntriesRef<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            iterator: self.iterator.clone(),
+        }
+    }
+}
+
+impl<'a, T> Iterator for EntriesRef<'a, T> {
+    type Item = (&'a Idx, &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iterator
+            .next()
+            .map(|(inner, value)| (Idx::from_inner(inner), value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iterator.size_hint()
+    }
 }
 
+impl<T> DoubleEndedIterator for EntriesRef<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iterator
+            .next_back()
+            .map(|(inner, value)| (Idx::from_inner(inner), value))
+    }
+}
+
+/// Iterator over handles and their values, returned by [`Arena::entries`].
 pub struct Entries<'a, T> {
-    iterator: std::slice::Iter<'a, (Arc<IdxInner>, T)>,
+    iterator: std::iter::Zip<std::slice::Iter<'a, Arc<IdxInner>>, std::slice::Iter<'a, T>>,
+}
+
+impl<T> Clone for Entries<'_, T> {
+    fn clone(&self) -> Self {
+        Self {
+            iterator: self.iterator.clone(),
+        }
+    }
 }
 
 impl<'a, T> Iterator for EntriesMut<'a, T> {
@@ -136,6 +322,19 @@ impl<'a, T> Iterator for EntriesMut<'a, T> {
     }
 }
 
+impl<T> DoubleEndedIterator for EntriesMut<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iterator.next_back().map(|(inner, value)| {
+            (
+                Idx {
+                    inner: inner.clone(),
+                },
+                value,
+            )
+        })
+    }
+}
+
 impl<'a, T> Iterator for Entries<'a, T> {
     type Item = (Idx, &'a T);
     fn next(&mut self) -> Option<Self::Item> {
@@ -150,22 +349,151 @@ impl<'a, T> Iterator for Entries<'a, T> {
     }
 }
 
+impl<T> DoubleEndedIterator for Entries<'_, T> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iterator.next_back().map(|(inner, value)| {
+            (
+                Idx {
+                    inner: inner.clone(),
+                },
+                value,
+            )
+        })
+    }
+}
+
+/// Consuming iterator over handles and their values, returned by [`Arena::into_entries`].
+pub struct IntoEntries<T> {
+    handles: std::vec::IntoIter<Arc<IdxInner>>,
+    values: std::vec::IntoIter<T>,
+}
+
+impl<T> Iterator for IntoEntries<T> {
+    type Item = (Idx, T);
+    fn next(&mut self) -> Option<Self::Item> {
+        let inner = self.handles.next()?;
+        let value = self.values.next()?;
+
+        // The value has left the arena, so the handle is only good as a key from here on
+        inner.removed.store(true, Ordering::Release);
+        Some((Idx { inner }, value))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.values.size_hint()
+    }
+}
+
+impl<T> Drop for IntoEntries<T> {
+    fn drop(&mut self) {
+        for inner in self.handles.by_ref() {
+            inner.removed.store(true, Ordering::Release);
+        }
+    }
+}
+
 impl<T> FromIterator<T> for Arena<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        // Reserve everything up front when the length is known exactly, so the values never have
+        // to be moved while collecting. Otherwise the upper bound can be far too big, or even
+        // `usize::MAX`, so only reserve the lower bound and grow from there like `Vec` does.
+        let iter = iter.into_iter();
+        let capacity = match iter.size_hint() {
+            (lower, Some(upper)) if lower == upper => upper,
+            (lower, _) => lower,
+        };
+        let mut values = Vec::with_capacity(capacity);
+        values.extend(iter);
+        Arena::from(values)
+    }
+}
+
+/// Rebuilds an arena from handles and values, such as the output of [`Arena::into_entries`]. The
+/// handles are reused, so handles kept from before resolve to the same values in the new arena.
+///
+/// Handles keep their stable ids, except where handles from different arenas share one. Those
+/// after the first get a new id, so ids stay unique within the arena.
+///
+/// # Panics
+///
+/// Panics if a handle hasn't been removed from its arena, or appears more than once.
+impl<T> FromIterator<(Idx, T)> for Arena<T> {
+    fn from_iter<I: IntoIterator<Item = (Idx, T)>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut arena = Arena::with_capacity(iter.size_hint().0);
+        let mut stable_ids = std::collections::HashSet::new();
+        let mut collisions = Vec::new();
+
+        for (idx, value) in iter {
+            let inner = idx.inner;
+            assert!(
+                inner.removed.load(Ordering::Acquire),
+                "Trying to collect a handle that is still in an arena!"
+            );
+
+            inner.index.store(arena.values.len(), Ordering::Release);
+            inner.arena_id.store(arena.id, Ordering::Relaxed);
+            inner.removed.store(false, Ordering::Release);
+
+            let stable_id = inner.stable_id.load(Ordering::Relaxed);
+            if stable_ids.insert(stable_id) {
+                let next = stable_id
+                    .checked_add(1)
+                    .expect("Trying to collect a handle with a stable id of u64::MAX!");
+                arena.next_stable_id = arena.next_stable_id.max(next);
+            } else {
+                collisions.push(arena.handles.len());
+            }
+
+            arena.handles.push(inner);
+            arena.values.push(value);
+        }
+
+        // New ids are only handed out once every kept id is known, so they can't collide either
+        for position in collisions {
+            let stable_id = arena.take_stable_id();
+            arena.handles[position]
+                .stable_id
+                .store(stable_id, Ordering::Relaxed);
+        }
+
+        arena
+    }
+}
+
+impl<T> From<Vec<T>> for Arena<T> {
+    fn from(values: Vec<T>) -> Self {
+        let len = values.len();
+        let id = next_arena_id();
+        let mut handles = Vec::with_capacity(values.capacity());
+        handles.extend((0..values.len()).map(|index| create_idx(index, index as u64, id)));
+
         Arena {
-            values: iter
-                .into_iter()
-                .enumerate()
-                .map(|(index, value)| (create_idx(index), value))
-                .collect(),
+            id,
+            handles,
+            values,
+            pool: Vec::new(),
+            max_capacity: None,
+            next_stable_id: len as u64,
+            reindex_observer: None,
+            pending_removals: Vec::new(),
         }
     }
 }
+
+fn next_arena_id() -> u64 {
+    // Always a std atomic, since this is a static and isn't part of what loom models
+    static NEXT_ARENA_ID: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+    NEXT_ARENA_ID.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+}
+
 #[inline]
-fn create_idx(index: usize) -> Arc<IdxInner> {
+fn create_idx(index: usize, stable_id: u64, arena_id: u64) -> Arc<IdxInner> {
     Arc::new(IdxInner {
         index: AtomicUsize::new(index),
         removed: AtomicBool::new(false),
+        stable_id: AtomicU64::new(stable_id),
+        arena_id: AtomicU64::new(arena_id),
     })
 }
 
@@ -176,23 +504,168 @@ impl<T> Arena<T> {
 
     pub fn with_capacity(capacity: usize) -> Arena<T> {
         Self {
+            id: next_arena_id(),
+            handles: Vec::with_capacity(capacity),
             values: Vec::with_capacity(capacity),
+            pool: Vec::new(),
+            max_capacity: None,
+            next_stable_id: 0,
+            reindex_observer: None,
+            pending_removals: Vec::new(),
+        }
+    }
+
+    /// Creates an arena with room for `capacity` values, and a handle ready for each of them. The
+    /// first `capacity` allocations don't allocate any memory.
+    pub fn with_full_capacity(capacity: usize) -> Arena<T> {
+        let mut arena = Self::with_capacity(capacity);
+        arena.pool.reserve_exact(capacity);
+
+        for _ in 0..capacity {
+            let inner = create_idx(0, 0, arena.id);
+            arena.release_idx(inner);
+        }
+
+        arena
+    }
+
+    pub fn from_fn<F: FnMut(usize) -> T>(n: usize, func: F) -> Arena<T> {
+        let mut arena = Self::with_capacity(n);
+        arena.alloc_n_with(n, func);
+        arena
+    }
+
+    /// Creates an arena holding just `value`, returning the arena and `value`'s handle.
+    pub fn singleton(value: T) -> (Arena<T>, Idx) {
+        let mut arena = Self::new();
+        let idx = arena.alloc(value);
+        (arena, idx)
+    }
+
+    /// Creates an arena that never holds more than `max` values.
+    ///
+    /// [`Arena::try_alloc`] hands the value back once the arena is full, while the other ways of
+    /// allocating panic.
+    pub fn with_max_capacity(max: usize) -> Arena<T> {
+        let mut arena = Self::with_capacity(DEFAULT_CAPACITY.min(max));
+        arena.max_capacity = Some(max);
+        arena
+    }
+
+    /// An id unique to this arena, shared by every handle it allocates. See [`Idx::arena_id`].
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn max_capacity(&self) -> Option<usize> {
+        self.max_capacity
+    }
+
+    fn is_full(&self) -> bool {
+        self.max_capacity
+            .is_some_and(|max| self.values.len() >= max)
+    }
+
+    pub fn try_alloc(&mut self, value: T) -> Result<Idx, T> {
+        if self.is_full() {
+            Err(value)
+        } else {
+            Ok(self.alloc(value))
+        }
+    }
+
+    /// Like [`Arena::try_alloc`], but also gives the value back if allocating it would mean
+    /// growing the arena's storage past [`Arena::capacity`].
+    ///
+    /// ```
+    /// # use nano_arena::Arena;
+    /// let mut arena = Arena::with_capacity(1);
+    /// assert!(arena.alloc_within_capacity(1).is_ok());
+    /// assert_eq!(arena.alloc_within_capacity(2), Err(2));
+    /// ```
+    pub fn alloc_within_capacity(&mut self, value: T) -> Result<Idx, T> {
+        if self.len() >= self.capacity() {
+            Err(value)
+        } else {
+            self.try_alloc(value)
         }
     }
 
+    /// The number of values the arena can hold before it has to reallocate. Values and handles
+    /// are stored separately, so this is the smaller of [`Arena::value_capacity`] and
+    /// [`Arena::handle_capacity`].
     pub fn capacity(&self) -> usize {
+        self.handles.capacity().min(self.values.capacity())
+    }
+
+    /// The capacity of the value storage. This is `usize::MAX` for zero sized values.
+    pub fn value_capacity(&self) -> usize {
         self.values.capacity()
     }
 
+    /// The capacity of the handle storage.
+    pub fn handle_capacity(&self) -> usize {
+        self.handles.capacity()
+    }
+
+    /// The bytes of storage each value takes up in the arena: the value itself plus the pointer
+    /// to its handle. The shared allocation behind each handle isn't included, see
+    /// [`Arena::memory_usage`].
+    pub fn element_stride(&self) -> usize {
+        std::mem::size_of::<T>() + std::mem::size_of::<Arc<IdxInner>>()
+    }
+
+    /// An estimate of the bytes held by the arena: the storage for values and handles, plus the
+    /// shared allocation behind each handle. Heap memory owned by the values isn't counted.
+    pub fn memory_usage(&self) -> usize {
+        // Each `Arc` allocation holds the strong and weak counts next to the `IdxInner`
+        let inner_size = std::mem::size_of::<IdxInner>() + 2 * std::mem::size_of::<usize>();
+        let handle_size = std::mem::size_of::<Arc<IdxInner>>();
+
+        self.values.capacity() * std::mem::size_of::<T>()
+            + (self.handles.capacity() + self.pool.capacity()) * handle_size
+            + (self.handles.len() + self.pool.len()) * inner_size
+    }
+
     #[inline]
     pub fn alloc_with_idx<F: FnOnce(Idx) -> T>(&mut self, func: F) -> Idx {
+        match self.try_alloc_with_idx(|idx| Ok::<T, std::convert::Infallible>(func(idx))) {
+            Ok(idx) => idx,
+            Err(never) => match never {},
+        }
+    }
+
+    /// Like [`Arena::alloc_with_idx`], but `func` can fail. If it does, nothing is allocated and
+    /// the handle passed to `func` is marked as removed.
+    pub fn try_alloc_with_idx<E, F: FnOnce(Idx) -> Result<T, E>>(
+        &mut self,
+        func: F,
+    ) -> Result<Idx, E> {
+        assert!(
+            !self.is_full(),
+            "Trying to allocate in an arena that is at its max capacity!"
+        );
+
         let len = self.values.len();
-        let inner = create_idx(len);
+        let inner = self.recycle_idx(len);
         let idx = Idx {
             inner: inner.clone(),
         };
-        self.values.push((inner.clone(), func(idx)));
-        Idx { inner }
+
+        match func(idx) {
+            Ok(value) => {
+                self.handles.push(inner.clone());
+                self.values.push(value);
+                Ok(Idx { inner })
+            }
+            Err(err) => {
+                // Nothing else can have allocated while `func` ran, so the stable id can be
+                // handed out again
+                self.next_stable_id -= 1;
+                self.release_idx(inner);
+                Err(err)
+            }
+        }
     }
 
     #[inline]
@@ -200,6 +673,7 @@ impl<T> Arena<T> {
         self.alloc_with_idx(|_| func())
     }
 
+    /// Alias for [`Arena::alloc`], named to match the standard collections.
     #[inline]
     pub fn insert(&mut self, value: T) -> Idx {
         self.alloc(value)
@@ -210,240 +684,1516 @@ impl<T> Arena<T> {
         self.alloc_with(|| value)
     }
 
+    pub fn alloc_many<I: IntoIterator<Item = T>>(&mut self, values: I) -> Vec<Idx> {
+        let values = values.into_iter();
+        self.handles.reserve(values.size_hint().0);
+        self.values.reserve(values.size_hint().0);
+        values.map(|value| self.alloc(value)).collect()
+    }
+
+    /// Allocates a clone of every value in `other`, each with a new handle.
+    pub fn extend_from_slice(&mut self, other: &[T])
+    where
+        T: Clone,
+    {
+        self.alloc_many(other.iter().cloned());
+    }
+
+    pub fn alloc_n_with<F: FnMut(usize) -> T>(&mut self, n: usize, mut func: F) -> Vec<Idx> {
+        self.handles.reserve(n);
+        self.values.reserve(n);
+        (0..n).map(|i| self.alloc(func(i))).collect()
+    }
+
+    fn insert_index(&mut self, index: usize, value: T) -> Idx {
+        assert!(
+            !self.is_full(),
+            "Trying to allocate in an arena that is at its max capacity!"
+        );
+
+        let inner = self.recycle_idx(index);
+        self.handles.insert(index, inner.clone());
+        self.values.insert(index, value);
+
+        // Everything after the new value shifts up by one
+        self.reindex(index + 1..self.handles.len());
+
+        Idx { inner }
+    }
+
+    /// Allocates `value` and moves it back until the value before it isn't greater, so a sorted
+    /// arena stays sorted. Equal values keep the order they were inserted in.
+    pub fn insert_sorted(&mut self, value: T) -> Idx
+    where
+        T: Ord,
+    {
+        let idx = self.alloc(value);
+        let mut position = self.values.len() - 1;
+
+        while position > 0 && self.values[position - 1] > self.values[position] {
+            self.swap_index(position - 1, position);
+            position -= 1;
+        }
+
+        idx
+    }
+
+    /// Allocates a value directly before `anchor`, shifting everything after it along. Returns
+    /// `None` if `anchor` has been removed.
+    pub fn insert_before<I: Borrow<Idx>>(&mut self, anchor: I, value: T) -> Option<Idx> {
+        self.position_of(anchor.borrow())
+            .map(|position| self.insert_index(position, value))
+    }
+
+    /// Allocates a value directly after `anchor`, shifting everything after it along. Returns
+    /// `None` if `anchor` has been removed.
+    pub fn insert_after<I: Borrow<Idx>>(&mut self, anchor: I, value: T) -> Option<Idx> {
+        self.position_of(anchor.borrow())
+            .map(|position| self.insert_index(position + 1, value))
+    }
+
+    fn recycle_idx(&mut self, index: usize) -> Arc<IdxInner> {
+        let stable_id = self.take_stable_id();
+
+        while let Some(mut inner) = self.pool.pop() {
+            // Inners that are still referenced by a handle are dropped from the pool, since
+            // reusing them would make that handle resolve to the new value.
+            if let Some(unique) = Arc::get_mut(&mut inner) {
+                unique.index.store(index, Ordering::Release);
+                unique.removed.store(false, Ordering::Release);
+                unique.stable_id.store(stable_id, Ordering::Relaxed);
+                // A removed handle can be collected into another arena while its inner is still
+                // in this pool
+                unique.arena_id.store(self.id, Ordering::Relaxed);
+                return inner;
+            }
+        }
+
+        create_idx(index, stable_id, self.id)
+    }
+
+    // Hands out the next stable id. `u64::MAX` is never handed out, so one past any id always
+    // fits.
+    fn take_stable_id(&mut self) -> u64 {
+        let stable_id = self.next_stable_id;
+        self.next_stable_id = stable_id
+            .checked_add(1)
+            .expect("Trying to allocate more values than there are stable ids!");
+        stable_id
+    }
+
+    fn release_idx(&mut self, inner: Arc<IdxInner>) {
+        inner.removed.store(true, Ordering::Release);
+        self.pool.push(inner);
+    }
+
     pub fn len(&self) -> usize {
         self.values.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The number of values that can be reached through a handle. Removals compact the storage,
+    /// so this is always the same as [`Arena::len`].
+    pub fn live_len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// The number of slots in storage that don't hold a live value. Removals compact the
+    /// storage, so this is always zero.
+    pub fn removed_count(&self) -> usize {
+        self.len() - self.live_len()
+    }
+
+    /// The positions in storage that don't hold a live value. Like [`Arena::removed_count`], this
+    /// is always empty while removals compact the storage.
+    pub fn removed_slots(&self) -> impl Iterator<Item = usize> + '_ {
+        self.handles
+            .iter()
+            .enumerate()
+            .filter(|(_, inner)| inner.removed.load(Ordering::Acquire))
+            .map(|(position, _)| position)
+    }
+
     pub fn get_idx_at_index(&self, index: usize) -> Option<Idx> {
-        self.values.get(index).map(|(inner, _)| Idx {
-            inner: Arc::clone(&inner),
+        self.handles.get(index).map(|inner| Idx {
+            inner: Arc::clone(inner),
         })
     }
 
+    pub fn get_at(&self, index: usize) -> Option<&T> {
+        self.values.get(index)
+    }
+
+    pub fn get_at_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.values.get_mut(index)
+    }
+
+    /// Borrows the value for `selected` mutably, along with an [`ArenaSplit`] that gives access to
+    /// the rest of the arena. Returns `None` if `selected` has been removed.
+    ///
+    /// The arena itself can't be used until both are dropped:
+    ///
+    /// ```compile_fail
+    /// # use nano_arena::Arena;
+    /// let mut arena = Arena::new();
+    /// let first = arena.alloc(1);
+    ///
+    /// let (value, _split) = arena.split_at(&first).unwrap();
+    /// arena.alloc(2);
+    /// *value += 1;
+    /// ```
     pub fn split_at<'a, I: Borrow<Idx>>(
         &'a mut self,
         selected: I,
-    ) -> Option<(&mut T, ArenaSplit<'a, T>)> {
+    ) -> Option<(&'a mut T, ArenaSplit<'a, T>)> {
         let selected = selected.borrow();
 
         if let Some(value) = self.get_mut(selected) {
+            // Safety: `value` points into the values' buffer, and the returned reference can't
+            // outlive the split, which holds the only other borrow of the arena. This relies on
+            // the split never touching the selected value while it's held:
+            //
+            // - It never hands out the selected value, and reaches every other value through a
+            //   raw pointer to its element. Indexing or iterating the values' slice would borrow
+            //   the selected value too, invalidating `value`.
+            // - It never lets the buffer move: it only allocates within the existing capacity,
+            //   and only removes values when that doesn't move a selected one. It debug asserts
+            //   that the buffer hasn't moved after each of those.
             Some((
                 unsafe { (value as *mut T).as_mut().unwrap() },
-                ArenaSplit {
-                    selected: selected.clone(),
-                    arena: self,
-                    __type: Default::default(),
-                },
+                ArenaSplit::new(vec![selected.clone()], self),
             ))
         } else {
             None
         }
     }
 
-    pub fn truncate(&mut self, len: usize) {
-        let end = self.values.len();
-        let start = end - (end - len);
-
-        for i in (start..end).rev() {
-            self.remove_index(i);
-        }
+    /// Like [`Arena::split_at`], but first reserves room for `additional` values to be allocated
+    /// through the returned [`ArenaSplit`].
+    pub fn split_at_with_capacity<'a, I: Borrow<Idx>>(
+        &'a mut self,
+        selected: I,
+        additional: usize,
+    ) -> Option<(&'a mut T, ArenaSplit<'a, T>)> {
+        self.handles.reserve(additional);
+        self.values.reserve(additional);
+        self.split_at(selected)
     }
 
-    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
-        let len = self.values.len();
-        let mut del = 0;
-
-        for i in 0..len {
-            if !f(&self.values[i].1) {
-                del += 1;
-            } else {
-                self.swap_index(i - del, i);
-            }
+    pub fn split_many<const N: usize, I: Borrow<Idx>>(
+        &mut self,
+        selected: [I; N],
+    ) -> Option<([&mut T; N], ArenaSplitMany<'_, T>)> {
+        let mut positions = [0; N];
+        for (position, idx) in positions.iter_mut().zip(selected.iter()) {
+            *position = self.position_of(idx.borrow())?;
         }
 
-        if del > 0 {
-            self.truncate(len - del);
+        // The split excludes values by handle, so it has to hold this arena's own handles
+        let selected: Vec<Idx> = positions
+            .iter()
+            .map(|position| Idx {
+                inner: Arc::clone(&self.handles[*position]),
+            })
+            .collect();
+
+        let mut sorted = positions;
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|pair| pair[0] == pair[1]) {
+            return None;
         }
+
+        let values = self.values.as_mut_ptr();
+
+        // Safety: every position is in bounds and unique, and the returned split refuses to hand
+        // out any of the selected values.
+        Some((
+            std::array::from_fn(|i| unsafe { &mut *values.add(positions[i]) }),
+            ArenaSplit::new(selected, self),
+        ))
     }
 
-    pub fn entries<'a>(&'a self) -> Entries<'a, T> {
-        Entries {
-            iterator: self.values.iter(),
+    /// Removes every value, marking all handles as removed.
+    pub fn clear(&mut self) {
+        for inner in self.handles.drain(..) {
+            inner.removed.store(true, Ordering::Release);
         }
+
+        self.values.clear();
     }
 
-    pub fn entries_mut<'a>(&'a mut self) -> EntriesMut<'a, T> {
-        EntriesMut {
-            iterator: self.values.iter_mut(),
+    /// Like [`Arena::clear`], but keeps the handle allocations around to be reused by the next
+    /// allocations, which saves an allocation for each value in an arena that is refilled often.
+    pub fn clear_reuse(&mut self) {
+        self.pool.reserve(self.handles.len());
+
+        while let Some(inner) = self.handles.pop() {
+            self.release_idx(inner);
         }
+
+        self.values.clear();
     }
 
-    pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a, T> {
-        IterMut {
-            iterator: self
-                .values
-                .iter_mut()
-                .map(&choose_second_member_of_tuple_mut),
-        }
+    /// Frees the handle allocations kept around for reuse by removals. Live handles aren't
+    /// affected, and later allocations create new handles until the pool fills up again.
+    pub fn shrink_pool(&mut self) {
+        self.pool = Vec::new();
     }
 
-    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
-        Iter {
-            iterator: self.values.iter().map(&choose_second_member_of_tuple_ref),
-        }
+    pub fn chunks(&self, size: usize) -> std::slice::Chunks<'_, T> {
+        self.values.chunks(size)
     }
 
-    pub fn to_vec(self) -> Vec<T> {
-        self.into()
+    pub fn chunks_mut(&mut self, size: usize) -> std::slice::ChunksMut<'_, T> {
+        self.values.chunks_mut(size)
     }
 
-    fn remove_index(&mut self, index: usize) -> T {
-        let (removed_index, value) = self.values.remove(index);
+    pub fn split_at_mut(&mut self, index: usize) -> (&mut [T], &mut [T]) {
+        self.values.split_at_mut(index)
+    }
 
-        for (index, (idx, _)) in self.values.iter().enumerate().skip(index) {
-            idx.index.store(index, Ordering::Relaxed);
+    /// Replaces the values in `range` with `replace_with`, like [`Vec::splice`], and returns the
+    /// values that were removed.
+    ///
+    /// The handles of the removed values are marked as removed, the new values get new handles,
+    /// and the values after `range` are reindexed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `range` is out of bounds, or if the arena would grow past its max capacity.
+    pub fn splice<I: IntoIterator<Item = T>>(
+        &mut self,
+        range: std::ops::Range<usize>,
+        replace_with: I,
+    ) -> Vec<T> {
+        let start = range.start;
+        let replace_with: Vec<T> = replace_with.into_iter().collect();
+
+        assert!(
+            self.max_capacity.is_none_or(|max| {
+                self.values.len() - range.len().min(self.values.len()) + replace_with.len() <= max
+            }),
+            "Trying to allocate in an arena that is at its max capacity!"
+        );
+
+        let removed: Vec<T> = self.values.drain(range.clone()).collect();
+        for inner in self.handles.drain(range).collect::<Vec<_>>() {
+            self.release_idx(inner);
         }
 
-        removed_index.removed.store(true, Ordering::Relaxed);
+        let inners: Vec<Arc<IdxInner>> = (0..replace_with.len())
+            .map(|offset| self.recycle_idx(start + offset))
+            .collect();
+        self.handles.splice(start..start, inners);
+        self.values.splice(start..start, replace_with);
 
-        value
+        // Everything after the new values has moved by the difference in length
+        self.reindex(start..self.handles.len());
+
+        removed
     }
 
-    pub fn remove<I: Borrow<Idx>>(&mut self, index: I) -> T {
-        if let Some(index) = index.borrow().value() {
-            self.remove_index(index)
-        } else {
-            panic!("Trying to remove index that has already been removed!");
+    /// Removes every value from `len` onwards. Does nothing if the arena is already shorter.
+    ///
+    /// Nothing before `len` moves, so this is a single pass over the removed handles.
+    pub fn truncate(&mut self, len: usize) {
+        if len >= self.values.len() {
+            return;
+        }
+
+        self.values.truncate(len);
+        for inner in self.handles.split_off(len) {
+            self.release_idx(inner);
         }
     }
 
-    fn swap_index(&mut self, a: usize, b: usize) {
-        self.values.swap(a, b);
-        self.values[a].0.index.store(a, Ordering::Relaxed);
-        self.values[b].0.index.store(b, Ordering::Relaxed);
+    pub fn retain<F: FnMut(&T) -> bool>(&mut self, mut f: F) {
+        self.retain_mut(|value| f(value));
     }
 
-    pub fn swap<A: Borrow<Idx>, B: Borrow<Idx>>(&mut self, a: A, b: B) {
-        if let Some((a_index, b_index)) = a
-            .borrow()
-            .value()
-            .and_then(|a| b.borrow().value().map(|b| (a, b)))
-        {
-            self.swap_index(a_index, b_index);
+    pub fn retain_mut<F: FnMut(&mut T) -> bool>(&mut self, mut f: F) {
+        let len = self.values.len();
+        let mut del = 0;
+
+        for i in 0..len {
+            if !f(&mut self.values[i]) {
+                del += 1;
+            } else {
+                self.swap_index(i - del, i);
+            }
+        }
+
+        if del > 0 {
+            self.truncate(len - del);
         }
     }
 
-    pub fn position<F: Fn(&T) -> bool>(&self, func: F) -> Option<Idx> {
-        for (inner, value) in self.values.iter() {
-            if func(value) {
-                return Some(Idx {
-                    inner: Arc::clone(&inner),
+    /// Splits the arena in two, moving the values `pred` returns `true` for into the first arena
+    /// and the rest into the second. Both arenas keep the original order. The values get new
+    /// handles, and the old ones are marked as removed.
+    pub fn partition<F: FnMut(&T) -> bool>(mut self, mut pred: F) -> (Arena<T>, Arena<T>) {
+        let mut matching = Arena::new();
+        let mut rest = Arena::new();
+
+        for value in std::mem::take(&mut self.values) {
+            if pred(&value) {
+                matching.alloc(value);
+            } else {
+                rest.alloc(value);
+            }
+        }
+
+        (matching, rest)
+    }
+
+    /// Keeps the values `pred` returns `true` for, and moves the rest into a new arena, which is
+    /// returned. Both arenas keep the original order.
+    ///
+    /// The moved values keep their handles, so a handle to a moved value refers to it in the
+    /// returned arena from then on.
+    pub fn partition_in_place<F: FnMut(&T) -> bool>(&mut self, mut pred: F) -> Arena<T> {
+        let handles = std::mem::take(&mut self.handles);
+        let values = std::mem::take(&mut self.values);
+
+        let mut rest = Arena::with_capacity(0);
+        rest.next_stable_id = self.next_stable_id;
+        self.handles.reserve(handles.len());
+        self.values.reserve(values.len());
+
+        for (inner, value) in handles.into_iter().zip(values) {
+            let arena = if pred(&value) { &mut *self } else { &mut rest };
+            inner.arena_id.store(arena.id, Ordering::Relaxed);
+            arena.handles.push(inner);
+            arena.values.push(value);
+        }
+
+        self.reindex(0..self.handles.len());
+        rest.reindex(0..rest.handles.len());
+
+        rest
+    }
+
+    /// Like [`Arena::retain`], but returns the handles of the removed values in their original
+    /// order.
+    pub fn retain_reporting<F: FnMut(&T) -> bool>(&mut self, mut f: F) -> Vec<Idx> {
+        let len = self.values.len();
+        let mut removed = Vec::new();
+
+        for i in 0..len {
+            // Swaps only move values back to before `i`, so `i` still holds the original value
+            if !f(&self.values[i]) {
+                removed.push(Idx {
+                    inner: Arc::clone(&self.handles[i]),
                 });
+            } else {
+                self.swap_index(i - removed.len(), i);
             }
         }
 
-        None
+        self.truncate(len - removed.len());
+        removed
     }
 
-    pub fn apply_ordering<I: Borrow<Idx>>(&mut self, ordering: &Vec<I>) {
-        assert!(ordering.len() == self.values.len());
+    /// Marks a value to be removed by the next [`Arena::flush_removals`]. Nothing moves until
+    /// then, so handles and positions stay valid. Marking a value more than once has no extra
+    /// effect.
+    pub fn mark_remove<I: Borrow<Idx>>(&mut self, index: I) {
+        self.pending_removals.push(index.borrow().clone());
+    }
 
-        let mut old_arena = Arena::<T>::with_capacity(self.capacity());
-        std::mem::swap(&mut old_arena.values, &mut self.values);
+    /// Removes every value marked with [`Arena::mark_remove`] in a single pass, keeping the order
+    /// of the rest. Returns the removed values in storage order. Marked values that were already
+    /// removed are skipped.
+    pub fn flush_removals(&mut self) -> Vec<T> {
+        let mut marked = vec![false; self.values.len()];
+        for idx in std::mem::take(&mut self.pending_removals) {
+            if let Some(position) = self.position_of(&idx) {
+                marked[position] = true;
+            }
+        }
 
-        for idx in ordering.iter() {
-            let new_index = self.values.len();
-            let old_index = idx.borrow().value().unwrap();
+        let removed_count = marked.iter().filter(|marked| **marked).count();
+        if removed_count == 0 {
+            return Vec::new();
+        }
 
-            let (inner, value) = old_arena.swap_remove_index(old_index);
+        let capacity = self.capacity();
+        let handles = std::mem::replace(&mut self.handles, Vec::with_capacity(capacity));
+        let values = std::mem::replace(&mut self.values, Vec::with_capacity(capacity));
+        let mut removed = Vec::with_capacity(removed_count);
+
+        for ((inner, value), marked) in handles.into_iter().zip(values).zip(marked) {
+            if marked {
+                self.release_idx(inner);
+                removed.push(value);
+            } else {
+                self.handles.push(inner);
+                self.values.push(value);
+            }
+        }
+
+        self.reindex(0..self.handles.len());
+        removed
+    }
 
-            inner.index.store(new_index, Ordering::Relaxed);
+    pub fn dedup_by<F: FnMut(&mut T, &mut T) -> bool>(&mut self, mut same: F) {
+        let len = self.values.len();
+        if len <= 1 {
+            return;
+        }
 
-            self.values.push((inner, value));
+        // Values before `kept` are the survivors, and are compared against the newest of them
+        let mut kept = 1;
 
-            idx.borrow().inner.index.store(new_index, Ordering::Relaxed);
+        for i in 1..len {
+            let (survivors, rest) = self.values.split_at_mut(i);
+            if !same(&mut rest[0], &mut survivors[kept - 1]) {
+                self.swap_index(kept, i);
+                kept += 1;
+            }
         }
+
+        self.truncate(kept);
     }
 
-    fn swap_remove_index(&mut self, index: usize) -> (Arc<IdxInner>, T) {
-        let (removed_index, value) = self.values.swap_remove(index);
+    pub fn dedup(&mut self)
+    where
+        T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b);
+    }
 
-        if self.values.len() > 0 && index != self.values.len() {
-            self.values[index].0.index.store(index, Ordering::Relaxed);
+    /// Yields a new handle along with each value. Creating a handle bumps an atomic reference
+    /// count, so prefer [`Arena::entries_ref`] when most of the handles aren't kept.
+    pub fn entries<'a>(&'a self) -> Entries<'a, T> {
+        Entries {
+            iterator: self.handles.iter().zip(self.values.iter()),
         }
+    }
 
-        (removed_index, value)
+    /// Like [`Arena::entries`], but lends out the arena's own handles instead of creating new
+    /// ones. Clone a handle to keep it.
+    pub fn entries_ref(&self) -> EntriesRef<'_, T> {
+        EntriesRef {
+            iterator: self.handles.iter().zip(self.values.iter()),
+        }
     }
 
-    #[cfg(test)]
-    fn get_index(&mut self, index: usize) -> &mut T {
-        &mut self.values[index].1
+    /// Yields each value along with its index, see [`Idx::value`]. Unlike [`Arena::entries`] this
+    /// doesn't create any handles, so it's cheaper when only the index is needed.
+    pub fn raw_entries(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.values.iter().enumerate()
     }
 
-    pub fn swap_remove<I: Borrow<Idx>>(&mut self, index: I) -> T {
-        if let Some(index) = index.borrow().value() {
-            let (removed_index, value) = self.swap_remove_index(index);
-            removed_index.removed.store(true, Ordering::Relaxed);
-            value
+    /// Like [`Arena::entries`], but only yields the values matching `pred`. Handles are only
+    /// created for the matches.
+    pub fn filter_entries<'a, F: Fn(&T) -> bool + 'a>(
+        &'a self,
+        pred: F,
+    ) -> impl Iterator<Item = (Idx, &'a T)> {
+        self.handles
+            .iter()
+            .zip(self.values.iter())
+            .filter(move |(_, value)| pred(value))
+            .map(|(inner, value)| {
+                (
+                    Idx {
+                        inner: Arc::clone(inner),
+                    },
+                    value,
+                )
+            })
+    }
+
+    pub fn entries_mut<'a>(&'a mut self) -> EntriesMut<'a, T> {
+        EntriesMut {
+            iterator: self.handles.iter().zip(self.values.iter_mut()),
+        }
+    }
+
+    pub fn iter_with_index(&self) -> impl Iterator<Item = (usize, Idx, &T)> {
+        self.entries()
+            .enumerate()
+            .map(|(index, (idx, value))| (index, idx, value))
+    }
+
+    pub fn for_each_entry<F: FnMut(Idx, &T)>(&self, mut func: F) {
+        for (idx, value) in self.entries() {
+            func(idx, value);
+        }
+    }
+
+    /// Like [`Arena::for_each_entry`], but stops at the first error and returns it.
+    pub fn try_for_each_entry<E, F: FnMut(Idx, &T) -> Result<(), E>>(
+        &self,
+        mut func: F,
+    ) -> Result<(), E> {
+        for (idx, value) in self.entries() {
+            func(idx, value)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn for_each_entry_mut<F: FnMut(Idx, &mut T)>(&mut self, mut func: F) {
+        for (idx, value) in self.entries_mut() {
+            func(idx, value);
+        }
+    }
+
+    pub fn iter_mut<'a>(&'a mut self) -> IterMut<'a, T> {
+        IterMut {
+            iterator: self.values.iter_mut(),
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> Iter<'a, T> {
+        Iter {
+            iterator: self.values.iter(),
+        }
+    }
+
+    /// Consumes the arena, returning its values in storage order.
+    ///
+    /// Every handle is marked as removed, so [`Idx::value`] returns `None` for them afterwards.
+    /// They still compare and hash the same, so use [`Arena::into_vec_keeping_handles`] to keep
+    /// track of which value each one belonged to.
+    pub fn to_vec(self) -> Vec<T> {
+        self.into()
+    }
+
+    /// Like [`Arena::to_vec`], but also returns the handle of each value, in the same order.
+    ///
+    /// The handles are marked as removed, but can still be used as keys:
+    ///
+    /// ```
+    /// # use nano_arena::Arena;
+    /// let mut arena = Arena::new();
+    /// let first = arena.alloc("first");
+    ///
+    /// let (values, handles) = arena.into_vec_keeping_handles();
+    /// assert_eq!(handles[0], first);
+    /// assert_eq!(values[0], "first");
+    /// assert!(first.value().is_none());
+    /// ```
+    pub fn into_vec_keeping_handles(mut self) -> (Vec<T>, Vec<Idx>) {
+        let handles = std::mem::take(&mut self.handles)
+            .into_iter()
+            .map(|inner| {
+                inner.removed.store(true, Ordering::Release);
+                Idx { inner }
+            })
+            .collect();
+
+        (std::mem::take(&mut self.values), handles)
+    }
+
+    pub fn into_hash_map(mut self) -> HashMap<Idx, T> {
+        let handles = std::mem::take(&mut self.handles);
+        let values = std::mem::take(&mut self.values);
+
+        handles
+            .into_iter()
+            .zip(values)
+            .map(|(inner, value)| {
+                inner.removed.store(true, Ordering::Release);
+                (Idx { inner }, value)
+            })
+            .collect()
+    }
+
+    /// Consumes the arena, yielding each handle along with its value. The handles are marked as
+    /// removed as they're yielded, but can still be compared and hashed.
+    pub fn into_entries(mut self) -> IntoEntries<T> {
+        IntoEntries {
+            handles: std::mem::take(&mut self.handles).into_iter(),
+            values: std::mem::take(&mut self.values).into_iter(),
+        }
+    }
+
+    pub fn as_entry_map(&self) -> HashMap<Idx, &T> {
+        self.entries().collect()
+    }
+
+    /// Returns the values in storage order, along with the stable id of each, see
+    /// [`Idx::stable_id`]. Store the ids alongside any references to values, then rebuild the
+    /// arena with [`Arena::deserialize_with_map`].
+    pub fn serialize_with_map(&self) -> (Vec<&T>, Vec<u64>) {
+        let ids = self
+            .handles
+            .iter()
+            .map(|inner| inner.stable_id.load(Ordering::Relaxed))
+            .collect();
+
+        (self.values.iter().collect(), ids)
+    }
+
+    /// Rebuilds an arena from the output of [`Arena::serialize_with_map`]. Each value keeps its
+    /// stable id, and the returned map gives the new handle for each id.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `values` and `ids` are different lengths, if an id appears more than once, or if
+    /// an id is `u64::MAX`, which is never handed out.
+    pub fn deserialize_with_map(values: Vec<T>, ids: Vec<u64>) -> (Arena<T>, HashMap<u64, Idx>) {
+        assert_eq!(
+            values.len(),
+            ids.len(),
+            "Trying to deserialize an arena with a different number of values and ids!"
+        );
+
+        let mut arena = Arena::from(values);
+        let mut map = HashMap::with_capacity(ids.len());
+
+        for (inner, id) in arena.handles.iter().zip(ids) {
+            inner.stable_id.store(id, Ordering::Relaxed);
+            let previous = map.insert(
+                id,
+                Idx {
+                    inner: Arc::clone(inner),
+                },
+            );
+            assert!(
+                previous.is_none(),
+                "Trying to deserialize an arena with a duplicate stable id!"
+            );
+        }
+
+        arena.next_stable_id = map
+            .keys()
+            .max()
+            .map_or(Some(0), |max| max.checked_add(1))
+            .expect("Trying to deserialize an arena with a stable id of u64::MAX!");
+
+        (arena, map)
+    }
+
+    pub fn into_boxed_slice(self) -> Box<[T]> {
+        Vec::from(self).into_boxed_slice()
+    }
+
+    fn remove_index(&mut self, index: usize) -> T {
+        let removed_index = self.handles.remove(index);
+        let value = self.values.remove(index);
+
+        // Everything after the removed value shifts down by one
+        self.reindex(index..self.handles.len());
+
+        self.release_idx(removed_index);
+
+        value
+    }
+
+    /// Removes a value, keeping the order of the rest of the arena.
+    ///
+    /// Every value after the removed one has to be moved and reindexed, so this is O(n). Use
+    /// [`Arena::remove_fast`] if the order doesn't matter.
+    pub fn remove<I: Borrow<Idx>>(&mut self, index: I) -> T {
+        let position = self.removable_position(index.borrow());
+        self.remove_index(position)
+    }
+
+    // Like `checked_position`, but panics with the reason the handle can't be removed.
+    fn removable_position(&self, index: &Idx) -> usize {
+        match self.checked_position(index) {
+            Ok(position) => position,
+            Err(ArenaError::Removed) => {
+                panic!("Trying to remove index that has already been removed!")
+            }
+            Err(ArenaError::ForeignArena) => {
+                panic!("Trying to remove index that belongs to another arena!")
+            }
+            Err(ArenaError::OutOfBounds) => panic!("Trying to remove index that is out of bounds!"),
+        }
+    }
+
+    /// Like [`Arena::remove`], but returns an error instead of panicking.
+    pub fn try_remove<I: Borrow<Idx>>(&mut self, index: I) -> Result<T, ArenaError> {
+        let position = self.checked_position(index.borrow())?;
+        Ok(self.remove_index(position))
+    }
+
+    /// Like [`Arena::remove`], but takes the position of the value instead of its handle.
+    /// Returns `None` if `index` is out of bounds.
+    pub fn remove_at(&mut self, index: usize) -> Option<T> {
+        if index < self.values.len() {
+            Some(self.remove_index(index))
         } else {
-            panic!("Trying to remove index that has already been removed!");
+            None
         }
     }
 
-    pub fn get<I: Borrow<Idx>>(&self, index: I) -> Option<&T> {
-        index
-            .borrow()
-            .value()
-            .and_then(|index| self.values.get(index).and_then(|(_, value)| Some(value)))
+    /// Removes every live handle in `handles` in a single pass, keeping the order of the rest of
+    /// the arena. The removed values are returned in the order of `handles`, skipping any that
+    /// were already removed or repeated.
+    pub fn remove_all<I: Borrow<Idx>>(&mut self, handles: &[I]) -> Vec<T> {
+        let len = self.values.len();
+
+        // Where each position's value should go in the returned vector, if it's being removed
+        let mut slots = vec![None; len];
+        let mut count = 0;
+
+        for idx in handles {
+            if let Some(position) = self.position_of(idx.borrow()) {
+                if slots[position].is_none() {
+                    slots[position] = Some(count);
+                    count += 1;
+                }
+            }
+        }
+
+        // Shift the survivors down over the removed values. Nothing is reindexed until the
+        // removed values are gone, so their inners still hold the position of their slot below.
+        let mut del = 0;
+        for (i, slot) in slots.iter().enumerate() {
+            if slot.is_some() {
+                del += 1;
+            } else if del > 0 {
+                self.handles.swap(i - del, i);
+                self.values.swap(i - del, i);
+            }
+        }
+
+        let mut removed: Vec<Option<T>> = (0..count).map(|_| None).collect();
+
+        for _ in 0..del {
+            let inner = self.handles.pop().unwrap();
+            let value = self.values.pop().unwrap();
+            let slot = slots[inner.index.load(Ordering::Acquire)].unwrap();
+            removed[slot] = Some(value);
+            self.release_idx(inner);
+        }
+
+        // Only the survivors after the first removed value moved
+        let first = slots.iter().position(Option::is_some).unwrap_or(len);
+        self.reindex(first..self.handles.len());
+
+        removed.into_iter().map(Option::unwrap).collect()
     }
 
-    pub fn get_mut<I: Borrow<Idx>>(&mut self, index: I) -> Option<&mut T> {
-        if let Some(index) = index.borrow().value() {
-            self.values
-                .get_mut(index)
-                .and_then(|(_, value)| Some(value))
+    /// Removes a value in O(1) by moving the last value into its place, so the order of the arena
+    /// isn't kept. This is the same as [`Arena::swap_remove`]. Use [`Arena::remove`] to keep the
+    /// order.
+    ///
+    /// ```
+    /// use nano_arena::Arena;
+    ///
+    /// let mut arena: Arena<&str> = vec!["a", "b", "c", "d"].into();
+    /// let b = arena.get_idx_at_index(1).unwrap();
+    ///
+    /// arena.remove_fast(b);
+    /// assert_eq!(arena.to_vec(), vec!["a", "d", "c"]);
+    /// ```
+    #[inline]
+    pub fn remove_fast<I: Borrow<Idx>>(&mut self, index: I) -> T {
+        self.swap_remove(index)
+    }
+
+    fn swap_index(&mut self, a: usize, b: usize) {
+        self.handles.swap(a, b);
+        self.values.swap(a, b);
+        self.reindex(a..a + 1);
+        self.reindex(b..b + 1);
+    }
+
+    /// Calls `observer` with every handle whose position changes from now on, along with its new
+    /// position. Handles that are allocated or removed aren't reported. Replaces any observer
+    /// that was already set.
+    pub fn set_reindex_observer(&mut self, observer: ReindexObserver) {
+        self.reindex_observer = Some(observer);
+    }
+
+    pub fn clear_reindex_observer(&mut self) {
+        self.reindex_observer = None;
+    }
+
+    // Stores the position of every handle in `range`, and tells the reindex observer about the
+    // ones that moved.
+    fn reindex(&mut self, range: std::ops::Range<usize>) {
+        let start = range.start;
+
+        for (offset, inner) in self.handles[range].iter().enumerate() {
+            let index = start + offset;
+
+            match &mut self.reindex_observer {
+                None => inner.index.store(index, Ordering::Release),
+                Some(observer) => {
+                    if inner.index.swap(index, Ordering::AcqRel) != index {
+                        observer(
+                            &Idx {
+                                inner: Arc::clone(inner),
+                            },
+                            index,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn swap<A: Borrow<Idx>, B: Borrow<Idx>>(&mut self, a: A, b: B) -> bool {
+        if let Some((a_index, b_index)) = self
+            .position_of(a.borrow())
+            .and_then(|a| self.position_of(b.borrow()).map(|b| (a, b)))
+        {
+            self.swap_index(a_index, b_index);
+            true
         } else {
+            false
+        }
+    }
+
+    /// Swaps the values at two positions, like [`slice::swap`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` are out of bounds.
+    pub fn swap_at(&mut self, a: usize, b: usize) {
+        self.swap_index(a, b);
+    }
+
+    pub fn reverse(&mut self) {
+        self.handles.reverse();
+        self.values.reverse();
+        self.reindex(0..self.handles.len());
+    }
+
+    pub fn position<F: Fn(&T) -> bool>(&self, func: F) -> Option<Idx> {
+        self.find(func).map(|(idx, _)| idx)
+    }
+
+    pub fn count<F: Fn(&T) -> bool>(&self, func: F) -> usize {
+        self.values.iter().filter(|value| func(value)).count()
+    }
+
+    pub fn positions<F: Fn(&T) -> bool>(&self, func: F) -> Vec<Idx> {
+        self.entries()
+            .filter(|(_, value)| func(value))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    pub fn rposition<F: Fn(&T) -> bool>(&self, func: F) -> Option<Idx> {
+        self.handles
+            .iter()
+            .zip(self.values.iter())
+            .rev()
+            .find_map(|(inner, value)| {
+                if func(value) {
+                    Some(Idx {
+                        inner: Arc::clone(inner),
+                    })
+                } else {
+                    None
+                }
+            })
+    }
+
+    pub fn find<F: Fn(&T) -> bool>(&self, func: F) -> Option<(Idx, &T)> {
+        self.find_map(|value| if func(value) { Some(value) } else { None })
+    }
+
+    pub fn find_mut<F: Fn(&T) -> bool>(&mut self, func: F) -> Option<(Idx, &mut T)> {
+        for (inner, value) in self.handles.iter().zip(self.values.iter_mut()) {
+            if func(value) {
+                return Some((
+                    Idx {
+                        inner: Arc::clone(inner),
+                    },
+                    value,
+                ));
+            }
+        }
+
+        None
+    }
+
+    pub fn find_map<'a, U, F: Fn(&'a T) -> Option<U>>(&'a self, func: F) -> Option<(Idx, U)> {
+        for (inner, value) in self.handles.iter().zip(self.values.iter()) {
+            if let Some(mapped) = func(value) {
+                return Some((
+                    Idx {
+                        inner: Arc::clone(inner),
+                    },
+                    mapped,
+                ));
+            }
+        }
+
+        None
+    }
+
+    // Position of the first value that `better` prefers over every value before it
+    fn best_position_by<F: FnMut(&T, &T) -> bool>(&self, mut better: F) -> Option<usize> {
+        let mut best = 0;
+
+        for (position, value) in self.values.iter().enumerate().skip(1) {
+            if better(value, &self.values[best]) {
+                best = position;
+            }
+        }
+
+        if self.values.is_empty() {
             None
+        } else {
+            Some(best)
         }
     }
-}
 
-impl<T> Into<Vec<T>> for Arena<T> {
-    fn into(self) -> Vec<T> {
-        // Set all the indexes to removed, since we can't use them anymore
-        for (idx, _) in self.values.iter() {
-            idx.removed.store(true, Ordering::Relaxed);
+    /// Checks that every pair of neighbouring values is in order, i.e. `compare` returns
+    /// `Some(Less)` or `Some(Equal)` for each.
+    pub fn is_sorted_by<F: FnMut(&T, &T) -> Option<std::cmp::Ordering>>(
+        &self,
+        mut compare: F,
+    ) -> bool {
+        self.values.windows(2).all(|pair| {
+            matches!(
+                compare(&pair[0], &pair[1]),
+                Some(std::cmp::Ordering::Less) | Some(std::cmp::Ordering::Equal)
+            )
+        })
+    }
+
+    pub fn is_sorted(&self) -> bool
+    where
+        T: Ord,
+    {
+        self.is_sorted_by(|a, b| Some(a.cmp(b)))
+    }
+
+    /// Returns the handle of the smallest value, or the first one if several are equally small.
+    pub fn min_by<F: FnMut(&T, &T) -> std::cmp::Ordering>(&self, mut compare: F) -> Option<Idx> {
+        self.best_position_by(|value, best| compare(value, best) == std::cmp::Ordering::Less)
+            .and_then(|position| self.get_idx_at_index(position))
+    }
+
+    /// Returns the handle of the largest value, or the first one if several are equally large.
+    pub fn max_by<F: FnMut(&T, &T) -> std::cmp::Ordering>(&self, mut compare: F) -> Option<Idx> {
+        self.best_position_by(|value, best| compare(value, best) == std::cmp::Ordering::Greater)
+            .and_then(|position| self.get_idx_at_index(position))
+    }
+
+    pub fn min_by_key<K: Ord, F: FnMut(&T) -> K>(&self, mut key: F) -> Option<Idx> {
+        self.min_by(|a, b| key(a).cmp(&key(b)))
+    }
+
+    pub fn max_by_key<K: Ord, F: FnMut(&T) -> K>(&self, mut key: F) -> Option<Idx> {
+        self.max_by(|a, b| key(a).cmp(&key(b)))
+    }
+
+    pub fn entry_or_alloc_with<F: FnOnce() -> T, P: Fn(&T) -> bool>(
+        &mut self,
+        pred: P,
+        default: F,
+    ) -> Idx {
+        self.get_or_alloc_with(pred, default).0
+    }
+
+    /// Returns the handle of the first value equal to `value`, which can be a borrowed form of
+    /// the values, like `&str` for an `Arena<String>`. This scans the arena.
+    pub fn get_by_value<Q: ?Sized + PartialEq>(&self, value: &Q) -> Option<Idx>
+    where
+        T: Borrow<Q>,
+    {
+        self.position(|existing| existing.borrow() == value)
+    }
+
+    /// Returns the handle of the first value equal to `value`, or allocates `value` if there isn't
+    /// one. This scans the arena, so it's O(n) per call.
+    pub fn intern(&mut self, value: T) -> Idx
+    where
+        T: PartialEq,
+    {
+        match self.position(|existing| *existing == value) {
+            Some(idx) => idx,
+            None => self.alloc(value),
         }
+    }
 
-        // Grab all the values and turn them into an array
-        self.values.into_iter().map(|(_, value)| value).collect()
+    /// Returns the handle of the first value matching `pred`, or allocates one with `func`. The
+    /// returned flag is `true` if a new value was allocated.
+    pub fn get_or_alloc_with<P: Fn(&T) -> bool, F: FnOnce() -> T>(
+        &mut self,
+        pred: P,
+        func: F,
+    ) -> (Idx, bool) {
+        if let Some(idx) = self.position(pred) {
+            (idx, false)
+        } else {
+            (self.alloc_with(func), true)
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::cell::Cell;
+    /// Returns the handles in their current storage order.
+    ///
+    /// Passing this back to [`Arena::apply_ordering`] restores the order at the time of the
+    /// snapshot, as long as nothing has been allocated or removed since:
+    ///
+    /// ```
+    /// # use nano_arena::Arena;
+    /// let mut arena: Arena<_> = vec![3, 1, 2].into_iter().collect();
+    ///
+    /// let snapshot = arena.ordering_snapshot();
+    /// arena.sort_by(|a, b| a.cmp(b));
+    /// arena.apply_ordering(&snapshot);
+    ///
+    /// assert_eq!(arena.to_vec(), vec![3, 1, 2]);
+    /// ```
+    pub fn ordering_snapshot(&self) -> Vec<Idx> {
+        self.handle_order()
+    }
 
-    fn setup_arena() -> (Arena<String>, Idx, Idx, Idx, Idx) {
-        let mut arena = Arena::new();
+    /// Returns the handle of every value in storage order, without touching the values.
+    ///
+    /// This is the input [`Arena::apply_ordering`] expects, so applying it straight away leaves
+    /// the arena as it is. Sorting or shuffling the result first and then applying it rearranges
+    /// the values to match.
+    pub fn handle_order(&self) -> Vec<Idx> {
+        self.handles
+            .iter()
+            .map(|inner| Idx {
+                inner: Arc::clone(inner),
+            })
+            .collect()
+    }
 
-        let john = arena.alloc("John".into());
-        let julia = arena.alloc("Julia".into());
-        let jane = arena.alloc("Jane".into());
-        let jake = arena.alloc("Jake".into());
+    /// Returns the handles of every value, for iterating while the arena is being changed.
+    ///
+    /// Look each handle up with [`Arena::get`] as it's reached. Values removed since the snapshot
+    /// was taken return `None`, and values allocated since aren't included.
+    pub fn snapshot(&self) -> Vec<Idx> {
+        self.ordering_snapshot()
+    }
 
-        (arena, john, julia, jane, jake)
+    pub fn sort_by<F: FnMut(&T, &T) -> std::cmp::Ordering>(&mut self, mut compare: F) {
+        let mut positions: Vec<usize> = (0..self.values.len()).collect();
+        positions.sort_by(|a, b| compare(&self.values[*a], &self.values[*b]));
+
+        let ordering: Vec<Idx> = positions
+            .into_iter()
+            .map(|position| Idx {
+                inner: Arc::clone(&self.handles[position]),
+            })
+            .collect();
+
+        self.apply_ordering(&ordering);
     }
 
-    #[test]
-    fn should_construct_default() {
-        let arena: Arena<()> = Default::default();
-        assert_eq!(arena.len(), 0);
-        assert_eq!(arena.capacity(), DEFAULT_CAPACITY);
+    pub fn apply_ordering<I: Borrow<Idx>>(&mut self, ordering: &[I]) {
+        if let Err(err) = self.try_apply_ordering(ordering) {
+            panic!("Trying to apply an invalid ordering: {}", err);
+        }
     }
 
-    #[test]
+    pub fn try_apply_ordering<I: Borrow<Idx>>(
+        &mut self,
+        ordering: &[I],
+    ) -> Result<(), OrderingError> {
+        // Work from the checked positions, since borrowing a handle again could give a different
+        // one
+        let sources = self.validate_ordering(ordering)?;
+        self.apply_permutation(|position| sources[position]);
+
+        Ok(())
+    }
+
+    /// Reorders the arena so the value at `perm[i]` moves to position `i`, like
+    /// [`Arena::apply_ordering`] with positions instead of handles.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `perm` isn't a permutation of `0..self.len()`.
+    pub fn permute(&mut self, perm: &[usize]) {
+        assert_eq!(
+            perm.len(),
+            self.values.len(),
+            "Trying to apply a permutation of the wrong length!"
+        );
+
+        let mut seen = vec![false; perm.len()];
+        for &position in perm {
+            assert!(
+                position < perm.len() && !seen[position],
+                "Trying to apply an invalid permutation!"
+            );
+            seen[position] = true;
+        }
+
+        self.apply_permutation(|position| perm[position]);
+    }
+
+    // Moves the value at `source(i)` to `i` for every position, then reindexes. `source` must be
+    // a valid permutation, and is called with positions in their original order.
+    fn apply_permutation<F: Fn(usize) -> usize>(&mut self, source: F) {
+        // Follow each cycle of the permutation, swapping every value into place using just a bit
+        // per value for bookkeeping
+        let mut visited = vec![false; self.values.len()];
+
+        for start in 0..self.values.len() {
+            if visited[start] {
+                continue;
+            }
+
+            let mut current = start;
+            loop {
+                visited[current] = true;
+                let from = source(current);
+                if from == start {
+                    break;
+                }
+                self.handles.swap(current, from);
+                self.values.swap(current, from);
+                current = from;
+            }
+        }
+
+        self.reindex(0..self.handles.len());
+    }
+
+    // The original `apply_ordering`, which moves every value into a second arena. Kept to check
+    // the in-place version against.
+    #[cfg(test)]
+    fn apply_ordering_by_copying<I: Borrow<Idx>>(&mut self, ordering: &[I]) {
+        self.validate_ordering(ordering).unwrap();
+
+        let mut old_arena = Arena::<T>::with_capacity(self.capacity());
+        std::mem::swap(&mut old_arena.handles, &mut self.handles);
+        std::mem::swap(&mut old_arena.values, &mut self.values);
+
+        for idx in ordering.iter() {
+            let new_index = self.values.len();
+            let old_index = idx.borrow().value().unwrap();
+
+            let (inner, value) = old_arena.swap_remove_index(old_index);
+
+            inner.index.store(new_index, Ordering::Release);
+
+            self.handles.push(inner);
+            self.values.push(value);
+
+            idx.borrow().inner.index.store(new_index, Ordering::Release);
+        }
+    }
+
+    // Checks that `ordering` is a permutation of this arena's handles, returning the current
+    // position of each.
+    fn validate_ordering<I: Borrow<Idx>>(
+        &self,
+        ordering: &[I],
+    ) -> Result<Vec<usize>, OrderingError> {
+        if ordering.len() != self.values.len() {
+            return Err(OrderingError::WrongLength {
+                expected: self.values.len(),
+                found: ordering.len(),
+            });
+        }
+
+        let mut seen = vec![false; ordering.len()];
+        let mut positions = Vec::with_capacity(ordering.len());
+
+        for (position, idx) in ordering.iter().enumerate() {
+            let idx = idx.borrow();
+            let index = idx.value().ok_or(OrderingError::Removed(position))?;
+
+            match self.handles.get(index) {
+                Some(inner) if Arc::ptr_eq(inner, &idx.inner) => {}
+                _ => return Err(OrderingError::ForeignArena(position)),
+            }
+
+            if seen[index] {
+                return Err(OrderingError::Duplicate(position));
+            }
+
+            seen[index] = true;
+            positions.push(index);
+        }
+
+        Ok(positions)
+    }
+
+    fn swap_remove_index(&mut self, index: usize) -> (Arc<IdxInner>, T) {
+        let removed_index = self.handles.swap_remove(index);
+        let value = self.values.swap_remove(index);
+
+        // `Vec::swap_remove` moves the old last element into `index`, unless `index` was the last
+        // position. In that case `index` is now equal to the new length and nothing moved, which
+        // also covers removing the only element.
+        if index < self.handles.len() {
+            self.reindex(index..index + 1);
+        }
+
+        (removed_index, value)
+    }
+
+    #[cfg(test)]
+    fn get_index(&mut self, index: usize) -> &mut T {
+        &mut self.values[index]
+    }
+
+    pub fn swap_remove<I: Borrow<Idx>>(&mut self, index: I) -> T {
+        let position = self.removable_position(index.borrow());
+        let (removed_index, value) = self.swap_remove_index(position);
+        self.release_idx(removed_index);
+        value
+    }
+
+    /// Like [`Arena::swap_remove`], but returns an error instead of panicking.
+    pub fn try_swap_remove<I: Borrow<Idx>>(&mut self, index: I) -> Result<T, ArenaError> {
+        let position = self.checked_position(index.borrow())?;
+        let (removed_index, value) = self.swap_remove_index(position);
+        self.release_idx(removed_index);
+        Ok(value)
+    }
+
+    /// Like [`Arena::swap_remove`], but takes the position of the value instead of its handle.
+    /// Returns `None` if `index` is out of bounds.
+    pub fn swap_remove_at(&mut self, index: usize) -> Option<T> {
+        if index < self.values.len() {
+            let (removed_index, value) = self.swap_remove_index(index);
+            self.release_idx(removed_index);
+            Some(value)
+        } else {
+            None
+        }
+    }
+
+    /// Like [`Arena::swap_remove`], but also returns the handle of the value that was moved into
+    /// the removed value's place, or `None` if the removed value was last.
+    pub fn swap_remove_returning<I: Borrow<Idx>>(&mut self, index: I) -> (T, Option<Idx>) {
+        let position = self.removable_position(index.borrow());
+        let (removed_index, value) = self.swap_remove_index(position);
+        self.release_idx(removed_index);
+        (value, self.get_idx_at_index(position))
+    }
+
+    pub(crate) fn position_of(&self, index: &Idx) -> Option<usize> {
+        index.value().filter(|position| {
+            self.handles
+                .get(*position)
+                .is_some_and(|inner| Arc::ptr_eq(inner, &index.inner))
+        })
+    }
+
+    /// The position of `index` in this arena, checked against the arena's own storage.
+    ///
+    /// Unlike [`Idx::value`], which can be read from any thread, the result always points at
+    /// the slot holding `index`'s value, or is `None` if `index` is removed or belongs to another
+    /// arena. It can't be stale, since the arena can't be reindexed while it's borrowed.
+    pub fn snapshot_value<I: Borrow<Idx>>(&self, index: I) -> Option<usize> {
+        self.position_of(index.borrow())
+    }
+
+    pub fn contains<I: Borrow<Idx>>(&self, index: I) -> bool {
+        self.position_of(index.borrow()).is_some()
+    }
+
+    /// Finds the value allocated with `stable_id`, see [`Idx::stable_id`]. This scans the arena.
+    pub fn get_by_stable_id(&self, stable_id: u64) -> Option<(Idx, &T)> {
+        self.handles
+            .iter()
+            .position(|inner| inner.stable_id.load(Ordering::Relaxed) == stable_id)
+            .and_then(|position| {
+                self.get_idx_at_index(position)
+                    .map(|idx| (idx, &self.values[position]))
+            })
+    }
+
+    /// Drops the entries of `map` whose handles aren't in this arena any more.
+    pub fn prune_secondary_map<V>(&self, map: &mut SecondaryMap<V>) {
+        map.retain(|idx, _| self.contains(idx));
+    }
+
+    pub fn get<I: Borrow<Idx>>(&self, index: I) -> Option<&T> {
+        self.try_get(index).ok()
+    }
+
+    /// Returns the position of `index` along with its value, or `None` if it's been removed.
+    pub fn locate<I: Borrow<Idx>>(&self, index: I) -> Option<(usize, &T)> {
+        self.position_of(index.borrow())
+            .map(|position| (position, &self.values[position]))
+    }
+
+    /// Like [`Arena::get`], but says why the value couldn't be found.
+    pub fn try_get<I: Borrow<Idx>>(&self, index: I) -> Result<&T, ArenaError> {
+        let position = self.checked_position(index.borrow())?;
+        Ok(&self.values[position])
+    }
+
+    fn checked_position(&self, index: &Idx) -> Result<usize, ArenaError> {
+        if index.arena_id() != self.id {
+            return Err(ArenaError::ForeignArena);
+        }
+
+        let position = index.value().ok_or(ArenaError::Removed)?;
+        if position >= self.values.len() {
+            return Err(ArenaError::OutOfBounds);
+        }
+
+        // The arena id alone isn't enough if an inner has been moved between arenas, so check
+        // the handle against the one stored at its position, like `position_of` does
+        match self.handles.get(position) {
+            Some(inner) if Arc::ptr_eq(inner, &index.inner) => Ok(position),
+            _ => Err(ArenaError::ForeignArena),
+        }
+    }
+
+    pub fn get_mut<I: Borrow<Idx>>(&mut self, index: I) -> Option<&mut T> {
+        let position = self.checked_position(index.borrow()).ok()?;
+        Some(&mut self.values[position])
+    }
+
+    /// Mutable references to two different values. Returns `None` if either handle is removed or
+    /// they refer to the same value.
+    pub fn get_pair_mut<A: Borrow<Idx>, B: Borrow<Idx>>(
+        &mut self,
+        a: A,
+        b: B,
+    ) -> Option<(&mut T, &mut T)> {
+        let a = self.position_of(a.borrow())?;
+        let b = self.position_of(b.borrow())?;
+
+        if a < b {
+            let (left, right) = self.values.split_at_mut(b);
+            Some((&mut left[a], &mut right[0]))
+        } else if b < a {
+            let (left, right) = self.values.split_at_mut(a);
+            Some((&mut right[0], &mut left[b]))
+        } else {
+            None
+        }
+    }
+
+    /// Looks up each of `ids` with [`Arena::get`], returning the results in the same order. The
+    /// same handle can appear more than once.
+    pub fn get_all<I: Borrow<Idx>>(&self, ids: &[I]) -> Vec<Option<&T>> {
+        ids.iter().map(|id| self.get(id.borrow())).collect()
+    }
+
+    pub fn get_disjoint_mut<I: Borrow<Idx>>(&mut self, ids: &[I]) -> Option<Vec<&mut T>> {
+        let mut positions = Vec::with_capacity(ids.len());
+
+        for id in ids {
+            positions.push(self.checked_position(id.borrow()).ok()?);
+        }
+
+        let mut sorted = positions.clone();
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|pair| pair[0] == pair[1]) {
+            return None;
+        }
+
+        let values = self.values.as_mut_ptr();
+
+        // Safety: every position is in bounds and unique, so each reference is to a different
+        // element and they can't alias.
+        Some(
+            positions
+                .into_iter()
+                .map(|position| unsafe { &mut *values.add(position) })
+                .collect(),
+        )
+    }
+
+    /// Returns the value for `index` without checking that it is live.
+    ///
+    /// # Safety
+    ///
+    /// `index` must not have been removed and must have been allocated by this arena.
+    /// Anything else is undefined behaviour.
+    pub unsafe fn get_unchecked<I: Borrow<Idx>>(&self, index: I) -> &T {
+        let index = index.borrow().inner.index.load(Ordering::Acquire);
+        self.values.get_unchecked(index)
+    }
+
+    /// Returns the value for `index` without checking that it is live.
+    ///
+    /// # Safety
+    ///
+    /// `index` must not have been removed and must have been allocated by this arena.
+    /// Anything else is undefined behaviour.
+    pub unsafe fn get_unchecked_mut<I: Borrow<Idx>>(&mut self, index: I) -> &mut T {
+        let index = index.borrow().inner.index.load(Ordering::Acquire);
+        self.values.get_unchecked_mut(index)
+    }
+}
+
+impl<T> From<Arena<T>> for Vec<T> {
+    fn from(mut arena: Arena<T>) -> Vec<T> {
+        // Dropping the arena sets all the indexes to removed, since we can't use them anymore
+        std::mem::take(&mut arena.values)
+    }
+}
+
+/// Indexes into the values in storage order, like [`Arena::get_at`]. Panics if `index` is out of
+/// bounds.
+impl<T> std::ops::Index<usize> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.values[index]
+    }
+}
+
+impl<T> std::ops::IndexMut<usize> for Arena<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.values[index]
+    }
+}
+
+/// Arenas compare by their values in storage order. Handles are ignored, so two arenas built by
+/// the same sequence of `alloc` calls are equal.
+impl<T: PartialEq> PartialEq for Arena<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.values == other.values
+    }
+}
+
+impl<T: Eq> Eq for Arena<T> {}
+
+/// Hashes the values in storage order, consistent with [`PartialEq`]. Two arenas built by the
+/// same sequence of `alloc` calls hash equal.
+impl<T: Hash> Hash for Arena<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.values.hash(state);
+    }
+}
+
+impl<T> Drop for Arena<T> {
+    fn drop(&mut self) {
+        // Handles can outlive the arena, so make sure they don't claim to point at anything
+        for idx in self.handles.iter() {
+            idx.removed.store(true, Ordering::Release);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn setup_arena() -> (Arena<String>, Idx, Idx, Idx, Idx) {
+        let mut arena = Arena::new();
+
+        let john = arena.alloc("John".into());
+        let julia = arena.alloc("Julia".into());
+        let jane = arena.alloc("Jane".into());
+        let jake = arena.alloc("Jake".into());
+
+        (arena, john, julia, jane, jake)
+    }
+
+    #[test]
+    fn should_construct_default() {
+        let arena: Arena<()> = Default::default();
+        assert_eq!(arena.len(), 0);
+        assert_eq!(arena.capacity(), DEFAULT_CAPACITY);
+    }
+
+    #[test]
     fn should_construct_with_capacity() {
         let arena: Arena<()> = Arena::with_capacity(100);
         assert_eq!(arena.len(), 0);
@@ -451,313 +2201,2864 @@ mod tests {
     }
 
     #[test]
-    fn getting_by_index() {
+    fn getting_by_index() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        assert_eq!(arena.get_index(0), "John");
+        assert_eq!(arena.get_index(1), "Julia");
+        assert_eq!(arena.get_index(2), "Jane");
+        assert_eq!(arena.get_index(3), "Jake");
+
+        assert_eq!(arena.get(john).unwrap(), "John");
+        assert_eq!(arena.get(julia).unwrap(), "Julia");
+        assert_eq!(arena.get(jane).unwrap(), "Jane");
+        assert_eq!(arena.get(jake).unwrap(), "Jake");
+    }
+
+    #[test]
+    fn arena_length() {
+        let (mut arena, _, _, _, _) = setup_arena();
+        assert_eq!(arena.len(), 4);
+        arena.alloc("Wow".into());
+        assert_eq!(arena.len(), 5);
+    }
+
+    #[test]
+    fn insert() {
+        let mut arena = Arena::new();
+
+        let first = arena.insert("First");
+        let second = arena.insert("Second");
+
+        assert_eq!(arena.len(), 2);
+        assert_eq!(*arena.get(&first).unwrap(), "First");
+        assert_eq!(*arena.get(&second).unwrap(), "Second");
+        assert_eq!(second.value(), Some(1));
+    }
+
+    #[test]
+    fn swap_indexes() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        assert_eq!(arena.get_index(0), "John");
+        assert_eq!(arena.get_index(1), "Julia");
+        assert_eq!(arena.get_index(2), "Jane");
+        assert_eq!(arena.get_index(3), "Jake");
+
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+
+        arena.swap(&john, &julia);
+        arena.swap(&jane, &jake);
+
+        assert_eq!(arena.get_index(0), "Julia");
+        assert_eq!(arena.get_index(1), "John");
+        assert_eq!(arena.get_index(2), "Jake");
+        assert_eq!(arena.get_index(3), "Jane");
+
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+    }
+
+    #[test]
+    fn remove() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        assert_eq!(arena.len(), 4);
+
+        arena.remove(&john);
+
+        assert!(arena.get(&john).is_none());
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+
+        assert_eq!(arena.get_index(0), "Julia");
+        assert_eq!(arena.get_index(1), "Jane");
+        assert_eq!(arena.get_index(2), "Jake");
+    }
+
+    #[test]
+    fn swap_remove() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        assert_eq!(arena.len(), 4);
+
+        arena.swap_remove(&john);
+
+        assert!(arena.get(&john).is_none());
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+
+        assert_eq!(arena.get_index(0), "Jake");
+        assert_eq!(arena.get_index(1), "Julia");
+        assert_eq!(arena.get_index(2), "Jane");
+    }
+
+    #[test]
+    fn remove_should_remove_last_value() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        assert_eq!(arena.len(), 4);
+
+        arena.swap_remove(&jake);
+        arena.remove(&jane);
+        arena.swap_remove(&julia);
+        arena.remove(&john);
+
+        assert_eq!(arena.len(), 0);
+    }
+
+    #[test]
+    fn convert_to_vec() {
+        let (arena, _, _, _, _) = setup_arena();
+        assert_eq!(arena.to_vec(), vec!["John", "Julia", "Jane", "Jake"]);
+    }
+
+    #[test]
+    fn convert_to_boxed_slice() {
+        let (arena, john, _, _, _) = setup_arena();
+        let (other, _, _, _, _) = setup_arena();
+
+        let boxed = arena.into_boxed_slice();
+
+        assert_eq!(boxed.into_vec(), other.to_vec());
+        assert!(john.value().is_none());
+    }
+
+    #[test]
+    fn index_should_be_hashable() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let mut seen = std::collections::HashSet::<Idx>::new();
+
+        seen.insert(jake.clone());
+        assert!(seen.contains(&jake));
+        assert_eq!(jake.value().unwrap(), 3);
+
+        arena.remove(john);
+        arena.remove(julia);
+        arena.remove(jane);
+
+        assert_eq!(jake.value().unwrap(), 0);
+        assert!(seen.contains(&jake));
+    }
+
+    #[test]
+    fn cloned_index_should_equal() {
+        let (_, john, _, _, _) = setup_arena();
+
+        let a = john.clone();
+        let b = john;
+
+        assert!(a == b);
+    }
+
+    #[test]
+    fn apply_ordering() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let ordering = vec![&jake, &julia, &john, &jane];
+
+        assert_eq!(arena.get_index(0), "John");
+        assert_eq!(arena.get_index(1), "Julia");
+        assert_eq!(arena.get_index(2), "Jane");
+        assert_eq!(arena.get_index(3), "Jake");
+
+        arena.apply_ordering(&ordering);
+
+        assert_eq!(arena.get_index(0), "Jake");
+        assert_eq!(arena.get_index(1), "Julia");
+        assert_eq!(arena.get_index(2), "John");
+        assert_eq!(arena.get_index(3), "Jane");
+
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+    }
+
+    #[test]
+    fn position() {
+        let (arena, _, julia, _, _) = setup_arena();
+
+        let j = arena.position(|v| v == "Julia").unwrap();
+
+        assert!(j == julia);
+    }
+
+    #[test]
+    fn entry_or_alloc_with() {
+        let (mut arena, _, _, _, _) = setup_arena();
+
+        let first = arena.entry_or_alloc_with(|v| v == "Jill", || "Jill".into());
+        let second = arena.entry_or_alloc_with(|v| v == "Jill", || "Jill".into());
+
+        assert!(first == second);
+        assert_eq!(arena.len(), 5);
+        assert_eq!(arena.get(&first).unwrap(), "Jill");
+    }
+
+    #[test]
+    fn rposition() {
+        let mut arena = Arena::new();
+
+        let first = arena.alloc("Twin");
+        arena.alloc("Other");
+        let last = arena.alloc("Twin");
+
+        assert!(arena.position(|v| *v == "Twin").unwrap() == first);
+        assert!(arena.rposition(|v| *v == "Twin").unwrap() == last);
+        assert!(arena.rposition(|v| *v == "None").is_none());
+    }
+
+    #[test]
+    fn find() {
+        let (arena, _, julia, _, _) = setup_arena();
+
+        let (idx, value) = arena.find(|v| v == "Julia").unwrap();
+
+        assert!(idx == julia);
+        assert_eq!(value, "Julia");
+        assert!(arena.find(|v| v == "Jill").is_none());
+    }
+
+    #[test]
+    fn find_map() {
+        let (arena, _, julia, _, _) = setup_arena();
+
+        let (idx, len) = arena
+            .find_map(|v| if v == "Julia" { Some(v.len()) } else { None })
+            .unwrap();
+
+        assert!(idx == julia);
+        assert_eq!(len, 5);
+    }
+
+    #[test]
+    fn truncate() {
+        let (mut arena, _, _, _, _) = setup_arena();
+        arena.truncate(0);
+        assert_eq!(arena.to_vec(), Vec::<String>::new());
+
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        arena.truncate(10);
+        assert_eq!(arena.len(), 4);
+
+        arena.truncate(2);
+        assert_eq!(arena.len(), 2);
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert_eq!(julia.value(), Some(1));
+        assert!(jane.is_removed());
+        assert!(jake.is_removed());
+
+        // The removed handles go back in the pool
+        let jill = arena.alloc("Jill".to_string());
+        assert_eq!(jill.value(), Some(2));
+        assert_eq!(arena.to_vec(), vec!["John", "Julia", "Jill"]);
+    }
+
+    #[test]
+    fn retain() {
+        let (mut arena, _, _, _, _) = setup_arena();
+
+        arena.retain(|v| v == "Julia" || v == "Jane");
+
+        assert_eq!(arena.to_vec(), vec!["Julia", "Jane"]);
+    }
+
+    #[test]
+    fn retain_mut() {
+        let mut arena = Arena::new();
+
+        let a = arena.alloc(0);
+        let b = arena.alloc(2);
+        let c = arena.alloc(1);
+
+        arena.retain_mut(|counter| {
+            *counter += 1;
+            *counter <= 2
+        });
+
+        assert!(arena.get(&b).is_none());
+        assert_eq!(*arena.get(&a).unwrap(), 1);
+        assert_eq!(*arena.get(&c).unwrap(), 2);
+        assert_eq!(a.value(), Some(0));
+        assert_eq!(c.value(), Some(1));
+        assert_eq!(arena.to_vec(), vec![1, 2]);
+    }
+
+    #[test]
+    fn mut_iter() {
+        let (mut arena, _, _, _, _) = setup_arena();
+
+        for val in arena.iter_mut() {
+            *val = "Wow".into();
+        }
+
+        assert_eq!(arena.to_vec(), vec!["Wow"; 4])
+    }
+
+    #[test]
+    fn iter() {
+        let (arena, _, _, _, _) = setup_arena();
+
+        let names = ["John", "Julia", "Jane", "Jake"];
+
+        for (a, b) in arena.iter().zip(names.iter()) {
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn turn_iterator_into_vector() {
+        let names = vec!["John", "Julia", "Jane", "Jake"];
+        let other_names = ["John", "Julia", "Jane", "Jake"];
+
+        let arena = names.into_iter().collect::<Arena<_>>();
+
+        for (a, b) in arena.iter().zip(other_names.iter()) {
+            assert_eq!(a, b);
+        }
+    }
+
+    #[test]
+    fn get_unchecked() {
+        let (mut arena, john, _, jane, _) = setup_arena();
+
+        fn checked_get<'a>(arena: &'a Arena<String>, idx: &Idx) -> Option<&'a String> {
+            if idx.value().is_some() {
+                Some(unsafe { arena.get_unchecked(idx) })
+            } else {
+                None
+            }
+        }
+
+        assert_eq!(checked_get(&arena, &john), arena.get(&john));
+        assert_eq!(checked_get(&arena, &jane), arena.get(&jane));
+
+        *unsafe { arena.get_unchecked_mut(&jane) } = "Not Jane".into();
+        assert_eq!(arena.get(&jane).unwrap(), "Not Jane");
+    }
+
+    #[test]
+    fn get_mut() {
+        let (mut arena, john, _, _, _) = setup_arena();
+        *(arena.get_mut(&john).unwrap()) = "Not John".into();
+        assert_eq!(arena.get(&john).unwrap(), "Not John");
+    }
+
+    struct Node {
+        id: Idx,
+    }
+
+    #[test]
+    fn alloc_with_idx() {
+        let mut arena = Arena::new();
+        let idx = arena.alloc_with_idx(|id| Node { id });
+        assert_eq!(arena.get(&idx).unwrap().id.value(), idx.value());
+    }
+
+    #[test]
+    fn split_at() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let (j, mut arena) = arena.split_at(&julia).unwrap();
+
+        assert_eq!(j, "Julia");
+        assert!(arena.get_mut(john).is_some());
+        assert!(arena.get_mut(jane).is_some());
+        assert!(arena.get_mut(jake).is_some());
+
+        assert!(arena.get_mut(julia).is_none());
+    }
+
+    #[test]
+    fn debug_printing() {
+        let (mut arena, john, _, _, _) = setup_arena();
+
+        let id = arena.id();
+
+        assert_eq!(format!("{:?}", john), format!("Idx ( arena = {}, 0 )", id));
+
+        arena.swap_remove(&john);
+
+        assert_eq!(
+            format!("{:?}", john),
+            format!("Removed Idx ( arena = {}, 0 )", id)
+        );
+    }
+
+    #[test]
+    fn compare_should_work_for_idx() {
+        let mut arena = Arena::new();
+        let left = arena.alloc(1);
+        let right = left.clone();
+
+        assert!(left.borrow() == right.borrow());
+    }
+
+    #[test]
+    fn splitting_should_be_safe() {
+        // A bug described here (https://github.com/bennetthardwick/nano-arena/issues/1) meant that
+        // multiple mutable references could be handed out.
+
+        struct ToggleIdx {
+            first: Idx,
+            second: Idx,
+            state: Cell<bool>,
+        }
+
+        impl Borrow<Idx> for ToggleIdx {
+            fn borrow(&self) -> &Idx {
+                self.state.set(!self.state.get());
+
+                if self.state.get() {
+                    &self.first
+                } else {
+                    &self.second
+                }
+            }
+        }
+
+        let mut arena = Arena::new();
+
+        let first = arena.alloc(1);
+        let second = arena.alloc(2);
+
+        let toggle_idx = ToggleIdx {
+            first: first.clone(),
+            second: second.clone(),
+            state: Cell::new(false),
+        };
+
+        let (first_mut_ref, mut split_arena) = arena.split_at(toggle_idx).unwrap();
+        let second_mut_ref = split_arena.get_mut(&first);
+
+        #[allow(dropping_references)]
+        drop(first_mut_ref);
+        assert!(second_mut_ref.is_none());
+    }
+
+    #[test]
+    fn removed_idx_should_be_recycled() {
+        let mut arena = Arena::new();
+
+        let first = arena.alloc(1);
+        let first_ptr = Arc::as_ptr(&first.inner);
+        arena.swap_remove(first);
+
+        let second = arena.alloc(2);
+        assert_eq!(Arc::as_ptr(&second.inner), first_ptr);
+        assert_eq!(second.value(), Some(0));
+        assert_eq!(*arena.get(&second).unwrap(), 2);
+    }
+
+    #[test]
+    fn recycling_should_not_resurrect_handles() {
+        let mut arena = Arena::new();
+
+        let first = arena.alloc(1);
+        arena.remove(&first);
+
+        let second = arena.alloc(2);
+
+        assert!(first != second);
+        assert!(first.value().is_none());
+        assert!(arena.get(&first).is_none());
+        assert_eq!(*arena.get(&second).unwrap(), 2);
+    }
+
+    #[test]
+    fn weak_idx_should_upgrade_while_alive() {
+        let weak = {
+            let mut arena = Arena::new();
+            let idx = arena.alloc(1);
+            let weak = idx.downgrade();
+
+            assert!(weak.upgrade().unwrap() == idx);
+            assert_eq!(
+                format!("{:?}", weak),
+                format!("WeakIdx ( arena = {}, 0 )", arena.id())
+            );
+
+            drop(idx);
+
+            // The arena still holds the slot, so the handle is still reachable
+            let upgraded = weak.upgrade().unwrap();
+            assert_eq!(*arena.get(&upgraded).unwrap(), 1);
+
+            weak
+        };
+
+        assert!(weak.upgrade().is_none());
+        assert_eq!(format!("{:?}", weak), "WeakIdx ( Dropped )");
+    }
+
+    #[test]
+    fn weak_idx_should_not_upgrade_into_recycled_slot() {
+        let mut arena = Arena::new();
+
+        let first = arena.alloc(1);
+        let weak = first.downgrade();
+        arena.swap_remove(first);
+
+        let second = arena.alloc(2);
+
+        // The weak handle keeps the old inner from being reused, and nothing else holds it, so
+        // it's gone by now
+        assert_eq!(arena.get(&second), Some(&2));
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn ordered_idx_should_sort_by_index() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        arena.swap(&john, &jake);
+        arena.remove(&julia);
+
+        let mut sorted = vec![
+            OrderedIdx(julia.clone()),
+            OrderedIdx(john.clone()),
+            OrderedIdx(jane.clone()),
+            OrderedIdx(jake.clone()),
+        ];
+        sorted.sort();
+
+        let sorted: Vec<Idx> = sorted.into_iter().map(|OrderedIdx(idx)| idx).collect();
+        assert_eq!(sorted, vec![jake, jane, john, julia]);
+    }
+
+    #[test]
+    fn contains() {
+        let (mut arena, john, julia, _, _) = setup_arena();
+        let (_other, foreign, _, _, _) = setup_arena();
+
+        assert!(arena.contains(&john));
+        assert!(arena.contains(&julia));
+
+        arena.remove(&john);
+
+        assert!(!arena.contains(&john));
+        assert!(arena.contains(&julia));
+        assert!(!arena.contains(&foreign));
+    }
+
+    #[test]
+    fn alloc_many() {
+        let mut arena = Arena::new();
+
+        let handles = arena.alloc_many(0..100);
+
+        assert_eq!(arena.len(), 100);
+        assert!(arena.capacity() >= 100);
+        for (i, idx) in handles.iter().enumerate() {
+            assert_eq!(*arena.get(idx).unwrap(), i);
+        }
+    }
+
+    #[test]
+    fn alloc_n_with() {
+        let (mut arena, _, _, _, _) = setup_arena();
+
+        let handles = arena.alloc_n_with(3, |i| format!("Clone {}", i));
+
+        assert_eq!(arena.len(), 7);
+        assert_eq!(handles.len(), 3);
+        assert_eq!(arena.get(&handles[0]).unwrap(), "Clone 0");
+        assert_eq!(arena.get(&handles[2]).unwrap(), "Clone 2");
+        assert_eq!(handles[2].value(), Some(6));
+    }
+
+    #[test]
+    fn get_disjoint_mut() {
+        let mut arena = Arena::new();
+        let handles = arena.alloc_many(0..8);
+
+        let selected = [
+            &handles[6],
+            &handles[0],
+            &handles[3],
+            &handles[1],
+            &handles[7],
+        ];
+        for value in arena.get_disjoint_mut(&selected).unwrap() {
+            *value *= 10;
+        }
+
+        assert_eq!(arena.to_vec(), vec![0, 10, 2, 30, 4, 5, 60, 70]);
+    }
+
+    #[test]
+    fn get_all() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        arena.remove(&julia);
+        arena.swap_remove(&jake);
+
+        let values = arena.get_all(&[&jane, &julia, &john, &jake, &jane]);
+        assert_eq!(
+            values,
+            vec![
+                Some(&"Jane".to_string()),
+                None,
+                Some(&"John".to_string()),
+                None,
+                Some(&"Jane".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn get_disjoint_mut_should_reject_aliasing() {
+        let (mut arena, john, julia, jane, _) = setup_arena();
+
+        assert!(arena.get_disjoint_mut(&[&john, &julia, &john]).is_none());
+
+        arena.remove(&jane);
+        assert!(arena.get_disjoint_mut(&[&john, &jane]).is_none());
+
+        let empty: &[Idx] = &[];
+        assert_eq!(arena.get_disjoint_mut(empty).unwrap().len(), 0);
+    }
+
+    #[test]
+    fn split_at_mut() {
+        let mut arena = Arena::new();
+        let handles = arena.alloc_many(0..6);
+
+        let (left, right) = arena.split_at_mut(2);
+        assert_eq!(left, &[0, 1]);
+        assert_eq!(right, &[2, 3, 4, 5]);
+
+        for value in left.iter_mut() {
+            *value += 10;
+        }
+
+        for value in right.iter_mut() {
+            *value += 100;
+        }
+
+        assert_eq!(arena.to_vec(), vec![10, 11, 102, 103, 104, 105]);
+        assert!(handles.iter().all(|idx| idx.value().is_none()));
+    }
+
+    #[test]
+    fn alloc_during_split() {
+        let (mut arena, _, julia, _, _) = setup_arena();
+
+        let (j, mut split) = arena.split_at_with_capacity(&julia, 2).unwrap();
+
+        let jill = split.alloc("Jill".into());
+        let jack = split.alloc_with_idx(|idx| format!("Jack {}", idx.value().unwrap()));
+
+        j.push_str(" Smith");
+
+        assert_eq!(split.get(&jill).unwrap(), "Jill");
+        assert_eq!(split.get(&jack).unwrap(), "Jack 5");
+        assert_eq!(j, "Julia Smith");
+        assert_eq!(arena.len(), 6);
+    }
+
+    #[test]
+    #[should_panic]
+    fn alloc_during_split_should_not_reallocate() {
+        let mut arena = Arena::with_capacity(1);
+        let first = arena.alloc(1);
+
+        let (_, mut split) = arena.split_at(&first).unwrap();
+        split.alloc(2);
+    }
+
+    #[test]
+    fn iterate_during_split() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let (j, mut split) = arena.split_at(&julia).unwrap();
+
+        for value in split.iter_mut() {
+            value.push('!');
+        }
+
+        assert_eq!(
+            split.iter().collect::<Vec<_>>(),
+            vec!["John!", "Jane!", "Jake!"]
+        );
+
+        let handles: Vec<Idx> = split.entries().map(|(idx, _)| idx).collect();
+        assert_eq!(handles, vec![john, jane, jake]);
+        assert_eq!(j, "Julia");
+    }
+
+    #[test]
+    fn nested_split_at() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let (first, mut split) = arena.split_at(&julia).unwrap();
+        assert!(split.split_at(&julia).is_none());
+
+        let (second, mut inner) = split.split_at(&jane).unwrap();
+
+        first.push_str(" Smith");
+        second.push_str(" Doe");
+
+        assert!(inner.get_mut(&julia).is_none());
+        assert!(inner.get_mut(&jane).is_none());
+        assert!(inner.get(&john).is_some());
+        assert_eq!(inner.iter().collect::<Vec<_>>(), vec!["John", "Jake"]);
+
+        inner.get_mut(&jake).unwrap().push('!');
+
+        assert_eq!(
+            arena.to_vec(),
+            vec!["John", "Julia Smith", "Jane Doe", "Jake!"]
+        );
+    }
+
+    #[test]
+    fn remove_during_split() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let (j, mut split) = arena.split_at(&julia).unwrap();
+
+        assert!(split.remove(&julia).is_none());
+        assert!(split.swap_remove(&julia).is_none());
+
+        // Removing John would shift Julia along
+        assert!(split.remove(&john).is_none());
+
+        assert_eq!(split.remove(&jane).unwrap(), "Jane");
+        assert_eq!(split.swap_remove(&john).unwrap(), "John");
+        assert!(split.remove(&jane).is_none());
+
+        j.push_str(" Smith");
+
+        assert!(jane.value().is_none());
+        assert!(john.value().is_none());
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        assert_eq!(arena.to_vec(), vec!["Jake", "Julia Smith"]);
+    }
+
+    #[test]
+    fn swap_remove_during_split_should_not_move_selected() {
+        let (mut arena, john, julia, _, jake) = setup_arena();
+
+        let (j, mut split) = arena.split_at(&jake).unwrap();
+
+        assert!(split.swap_remove(&john).is_none());
+        assert!(split.remove(&john).is_none());
+
+        j.push('!');
+
+        let (j, mut split) = arena.split_at(&john).unwrap();
+
+        assert_eq!(split.swap_remove(&julia).unwrap(), "Julia");
+
+        j.push('!');
+
+        assert_eq!(jake.value(), Some(1));
+        assert_eq!(arena.to_vec(), vec!["John!", "Jake!", "Jane"]);
+    }
+
+    #[test]
+    fn split_many() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let ([a, b], mut split) = arena.split_many([&jane, &john]).unwrap();
+
+        a.push('!');
+        b.push('?');
+
+        assert!(split.get(&jane).is_none());
+        assert!(split.get_mut(&john).is_none());
+        assert_eq!(split.iter().collect::<Vec<_>>(), vec!["Julia", "Jake"]);
+
+        let ([a, b, c], split) = arena.split_many([&jake, &julia, &john]).unwrap();
+
+        a.push('!');
+        b.push('!');
+        c.push('!');
+
+        assert!(split.get(&julia).is_none());
+        assert_eq!(split.iter().collect::<Vec<_>>(), vec!["Jane!"]);
+        assert_eq!(arena.to_vec(), vec!["John?!", "Julia!", "Jane!", "Jake!"]);
+    }
+
+    #[test]
+    fn split_many_should_reject_duplicates() {
+        let (mut arena, john, julia, jane, _) = setup_arena();
+
+        assert!(arena.split_many([&john, &john]).is_none());
+        assert!(arena.split_many([&john, &julia, &john]).is_none());
+
+        arena.remove(&jane);
+        assert!(arena.split_many([&john, &jane]).is_none());
+    }
+
+    #[test]
+    fn try_apply_ordering() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        assert!(arena
+            .try_apply_ordering(&[&jake, &jane, &julia, &john])
+            .is_ok());
+        assert_eq!(arena.to_vec(), vec!["Jake", "Jane", "Julia", "John"]);
+    }
+
+    #[test]
+    fn apply_ordering_should_borrow_each_handle_once() {
+        struct ToggleIdx {
+            first: Idx,
+            second: Idx,
+            state: Cell<bool>,
+        }
+
+        impl Borrow<Idx> for ToggleIdx {
+            fn borrow(&self) -> &Idx {
+                self.state.set(!self.state.get());
+
+                if self.state.get() {
+                    &self.first
+                } else {
+                    &self.second
+                }
+            }
+        }
+
+        let mut arena = Arena::new();
+        let first = arena.alloc(1);
+        let second = arena.alloc(2);
+
+        // Borrowing the second entry again would give `[first, first]`, which isn't a
+        // permutation
+        let ordering = [
+            ToggleIdx {
+                first: first.clone(),
+                second: first.clone(),
+                state: Cell::new(false),
+            },
+            ToggleIdx {
+                first: second.clone(),
+                second: first.clone(),
+                state: Cell::new(false),
+            },
+        ];
+
+        arena.apply_ordering(&ordering);
+
+        assert_eq!(first.value(), Some(0));
+        assert_eq!(second.value(), Some(1));
+        assert_eq!(arena.to_vec(), vec![1, 2]);
+    }
+
+    #[test]
+    fn try_apply_ordering_errors() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        let (_other, foreign, _, _, _) = setup_arena();
+
+        assert_eq!(
+            arena.try_apply_ordering(&[&john, &julia, &jane]),
+            Err(OrderingError::WrongLength {
+                expected: 4,
+                found: 3
+            })
+        );
+
+        assert_eq!(
+            arena.try_apply_ordering(&[&jake, &foreign, &julia, &john]),
+            Err(OrderingError::ForeignArena(1))
+        );
+
+        assert_eq!(
+            arena.try_apply_ordering(&[&jake, &jane, &julia, &jane]),
+            Err(OrderingError::Duplicate(3))
+        );
+
+        let removed = arena.alloc("Jill".into());
+        arena.remove(&removed);
+
+        assert_eq!(
+            arena.try_apply_ordering(&[&jake, &jane, &removed, &john]),
+            Err(OrderingError::Removed(2))
+        );
+
+        // Nothing should have moved
+        assert_eq!(arena.to_vec(), vec!["John", "Julia", "Jane", "Jake"]);
+    }
+
+    #[test]
+    #[should_panic(expected = "appears more than once")]
+    fn apply_ordering_should_reject_duplicates() {
+        let (mut arena, john, _, jane, jake) = setup_arena();
+
+        assert_eq!(
+            arena.try_apply_ordering(&[&jake, &jake, &john, &jane]),
+            Err(OrderingError::Duplicate(1))
+        );
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        assert_eq!(arena.len(), 4);
+
+        arena.apply_ordering(&[&jake, &jake, &john, &jane]);
+    }
+
+    #[test]
+    fn swap_should_report_success() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        assert!(arena.swap(&john, &jake));
+        assert_eq!(john.value(), Some(3));
+        assert_eq!(jake.value(), Some(0));
+
+        arena.remove(&julia);
+
+        assert!(!arena.swap(&julia, &jane));
+        assert!(!arena.swap(&jane, &julia));
+        assert_eq!(arena.to_vec(), vec!["Jake", "Jane", "John"]);
+    }
+
+    #[test]
+    fn swap_remove_returning() {
+        let (mut arena, john, julia, _, jake) = setup_arena();
+
+        let (value, moved) = arena.swap_remove_returning(&julia);
+        assert_eq!(value, "Julia");
+        assert!(moved.unwrap() == jake);
+        assert_eq!(jake.value(), Some(1));
+
+        let (value, moved) = arena.swap_remove_returning(&jake);
+        assert_eq!(value, "Jake");
+        assert!(moved.is_some());
+
+        let last = arena.get_idx_at_index(arena.len() - 1).unwrap();
+        let (_, moved) = arena.swap_remove_returning(&last);
+        assert!(moved.is_none());
+        assert_eq!(arena.to_vec(), vec!["John"]);
+        assert!(john.value().is_none());
+    }
+
+    #[test]
+    fn convert_to_hash_map() {
+        let (arena, john, julia, _, _) = setup_arena();
+
+        let map = arena.as_entry_map();
+        assert_eq!(map.len(), 4);
+        assert_eq!(map[&julia], "Julia");
+
+        let map = arena.into_hash_map();
+        assert_eq!(map.len(), 4);
+        assert_eq!(map[&john], "John");
+        assert!(john.value().is_none());
+    }
+
+    #[test]
+    fn try_alloc_should_respect_max_capacity() {
+        let mut arena = Arena::with_max_capacity(2);
+
+        let first = arena.try_alloc("First").unwrap();
+        arena.try_alloc("Second").unwrap();
+
+        assert_eq!(arena.try_alloc("Third"), Err("Third"));
+        assert_eq!(arena.len(), 2);
+
+        arena.remove(&first);
+        assert!(arena.try_alloc("Third").is_ok());
+        assert_eq!(arena.max_capacity(), Some(2));
+    }
+
+    #[test]
+    #[should_panic]
+    fn alloc_should_panic_past_max_capacity() {
+        let mut arena = Arena::with_max_capacity(1);
+
+        arena.alloc(1);
+        arena.alloc(2);
+    }
+
+    #[test]
+    fn from_fn() {
+        let arena = Arena::from_fn(5, |i| i * i);
+
+        assert_eq!(arena.len(), 5);
+        assert!(arena.capacity() >= 5);
+
+        let idx = arena.get_idx_at_index(3).unwrap();
+        assert_eq!(*arena.get(&idx).unwrap(), 9);
+        assert_eq!(arena.to_vec(), vec![0, 1, 4, 9, 16]);
+    }
+
+    #[test]
+    fn convert_from_vec() {
+        let mut values = Vec::with_capacity(10);
+        values.extend(vec!["John", "Julia", "Jane", "Jake"]);
+
+        let arena = Arena::from(values);
+        assert_eq!(arena.capacity(), 10);
+
+        let jane = arena.get_idx_at_index(2).unwrap();
+        assert_eq!(*arena.get(&jane).unwrap(), "Jane");
+
+        let back: Vec<_> = arena.into();
+        assert_eq!(back, vec!["John", "Julia", "Jane", "Jake"]);
+    }
+
+    #[test]
+    fn live_len_and_removed_count() {
+        let (mut arena, john, julia, jane, _) = setup_arena();
+
+        assert_eq!(arena.live_len(), 4);
+        assert_eq!(arena.removed_count(), 0);
+
+        arena.remove(&john);
+        arena.swap_remove(&julia);
+
+        assert_eq!(arena.len(), 2);
+        assert_eq!(arena.live_len(), 2);
+        assert_eq!(arena.removed_count(), 0);
+
+        arena.retain(|_| false);
+
+        assert!(jane.value().is_none());
+        assert_eq!(arena.live_len(), 0);
+        assert_eq!(arena.removed_count(), 0);
+    }
+
+    #[test]
+    fn memory_usage() {
+        let mut arena = Arena::<u64>::with_capacity(0);
+        assert_eq!(arena.memory_usage(), 0);
+
+        arena.alloc_many(vec![1, 2]);
+        let allocated = arena.memory_usage();
+        assert!(allocated >= 2 * (std::mem::size_of::<u64>() + std::mem::size_of::<IdxInner>()));
+
+        arena.handles.reserve(100);
+        arena.values.reserve(100);
+        assert!(arena.memory_usage() >= allocated + 100 * std::mem::size_of::<u64>());
+    }
+
+    #[test]
+    fn remove_fast() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        assert_eq!(arena.remove_fast(&john), "John");
+
+        assert!(john.value().is_none());
+        assert_eq!(jake.value(), Some(0));
+        assert_eq!(julia.value(), Some(1));
+        assert_eq!(jane.value(), Some(2));
+    }
+
+    #[test]
+    fn iter_with_index() {
+        let (mut arena, john, _, _, jake) = setup_arena();
+
+        arena.swap(&john, &jake);
+
+        let names: Vec<_> = arena
+            .iter_with_index()
+            .map(|(index, idx, value)| {
+                assert_eq!(idx.value(), Some(index));
+                value.as_str()
+            })
+            .collect();
+
+        assert_eq!(names, vec!["Jake", "Julia", "Jane", "John"]);
+    }
+
+    #[test]
+    fn dedup() {
+        let mut arena = Arena::new();
+
+        let first = arena.alloc("a");
+        let duplicate = arena.alloc("a");
+        let b = arena.alloc("b");
+        let last = arena.alloc("a");
+
+        arena.dedup();
+
+        assert!(duplicate.value().is_none());
+        assert_eq!(first.value(), Some(0));
+        assert_eq!(b.value(), Some(1));
+        assert_eq!(last.value(), Some(2));
+        assert_eq!(arena.to_vec(), vec!["a", "b", "a"]);
+    }
+
+    #[test]
+    fn dedup_by() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        arena.dedup_by(|a, b| a.len() == b.len());
+
+        assert!(julia.value().is_some());
+        assert!(jane.value().is_some());
+        assert!(jake.value().is_none());
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.to_vec(), vec!["John", "Julia", "Jane"]);
+    }
+
+    #[test]
+    fn remove_all() {
+        let mut arena = Arena::new();
+        let handles = arena.alloc_many(0..8);
+
+        arena.remove(&handles[7]);
+
+        let removed = arena.remove_all(&[
+            &handles[5],
+            &handles[1],
+            &handles[7],
+            &handles[2],
+            &handles[5],
+        ]);
+
+        assert_eq!(removed, vec![5, 1, 2]);
+        assert_eq!(arena.iter().collect::<Vec<_>>(), vec![&0, &3, &4, &6]);
+
+        for (i, idx) in handles.iter().enumerate() {
+            match i {
+                1 | 2 | 5 | 7 => assert!(idx.value().is_none()),
+                _ => assert_eq!(arena.get(idx), Some(&i)),
+            }
+        }
+
+        assert_eq!(handles[6].value(), Some(3));
+    }
+
+    #[test]
+    fn positions() {
+        let (arena, john, _, jane, _) = setup_arena();
+
+        assert_eq!(
+            arena.positions(|v| v.starts_with("Jo") || v == "Jane"),
+            vec![john, jane]
+        );
+        assert!(arena.positions(|v| v.starts_with('X')).is_empty());
+    }
+
+    #[test]
+    fn min_and_max() {
+        let (arena, john, julia, _, jake) = setup_arena();
+
+        assert!(arena.min_by_key(|v| v.len()).unwrap() == john);
+        assert!(arena.max_by_key(|v| v.len()).unwrap() == julia);
+        assert!(arena.min_by(|a, b| a.cmp(b)).unwrap() == jake);
+        assert!(
+            arena
+                .max_by(|a, b| a.len().cmp(&b.len()).reverse())
+                .unwrap()
+                == john
+        );
+
+        let empty = Arena::<String>::new();
+        assert!(empty.min_by_key(|v| v.len()).is_none());
+    }
+
+    // Applies operations to both an arena and a plain `Vec`, checking that they agree and that
+    // every handle ever handed out still resolves to the right value.
+    fn check_against_model(operations: Vec<Operation<u32>>) {
+        let mut arena = Arena::new();
+        let mut model = Vec::new();
+        let mut handles = Vec::new();
+
+        for operation in operations {
+            if let Some(idx) = operation.clone().apply(&mut arena) {
+                handles.push(idx);
+            }
+
+            operation.apply_to_vec(&mut model);
+
+            assert_eq!(
+                arena.iter().collect::<Vec<_>>(),
+                model.iter().collect::<Vec<_>>()
+            );
+
+            for idx in handles.iter() {
+                match idx.value() {
+                    Some(position) => {
+                        assert!(arena.contains(idx));
+                        assert_eq!(arena.get(idx), model.get(position));
+                    }
+                    None => assert!(arena.get(idx).is_none()),
+                }
+            }
+
+            // Values are unique, so the number of live handles has to match the model
+            assert_eq!(
+                handles.iter().filter(|idx| idx.value().is_some()).count(),
+                model.len()
+            );
+        }
+    }
+
+    #[test]
+    fn operations_should_match_model() {
+        // A small xorshift generator, so the sequence is the same on every run
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as usize
+        };
+
+        let mut operations = Vec::new();
+        for value in 0..2000 {
+            operations.push(match next() % 6 {
+                0 | 1 => Operation::Alloc(value),
+                2 => Operation::Remove(next()),
+                3 => Operation::SwapRemove(next()),
+                4 => Operation::Swap(next(), next()),
+                _ => Operation::ApplyOrdering((0..next() % 16).map(|_| next() % 8).collect()),
+            });
+        }
+
+        check_against_model(operations);
+    }
+
+    #[cfg(feature = "arbitrary")]
+    #[test]
+    fn arbitrary_operations_should_match_model() {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let bytes: Vec<u8> = (0..4096u32)
+            .map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect();
+        let mut u = Unstructured::new(&bytes);
+
+        let arena = Arena::<u32>::arbitrary(&mut u).unwrap();
+        assert!(arena
+            .iter()
+            .zip(arena.entries())
+            .all(|(a, (idx, b))| a == b && arena.contains(idx)));
+
+        // Give every allocation a unique value, so handles can be checked against the model
+        let operations: Vec<Operation<u32>> = Vec::<Operation<u32>>::arbitrary(&mut u)
+            .unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(i, operation)| match operation {
+                Operation::Alloc(_) => Operation::Alloc(i as u32),
+                other => other,
+            })
+            .collect();
+
+        check_against_model(operations);
+    }
+
+    #[test]
+    fn clear() {
+        let (mut arena, john, _, _, jake) = setup_arena();
+
+        arena.clear();
+
+        assert!(arena.is_empty());
+        assert!(john.value().is_none());
+        assert!(jake.value().is_none());
+        assert!(arena.get(&jake).is_none());
+    }
+
+    #[test]
+    fn clear_reuse() {
+        let mut arena = Arena::new();
+
+        let pointers: Vec<_> = arena
+            .alloc_many(0..4)
+            .iter()
+            .map(|idx| Arc::as_ptr(&idx.inner))
+            .collect();
+
+        let kept = arena.get_idx_at_index(3).unwrap();
+        arena.clear_reuse();
+
+        assert!(arena.is_empty());
+        assert!(kept.value().is_none());
+
+        let handles = arena.alloc_many(10..14);
+
+        // The handle that's still held can't be reused
+        for (idx, pointer) in handles.iter().zip(pointers.iter()).take(3) {
+            assert_eq!(Arc::as_ptr(&idx.inner), *pointer);
+        }
+        assert!(handles[3] != kept);
+        assert!(kept.value().is_none());
+        assert_eq!(arena.to_vec(), vec![10, 11, 12, 13]);
+    }
+
+    #[test]
+    fn get_or_alloc_with() {
+        let mut arena = Arena::<String>::new();
+
+        let (first, allocated) = arena.get_or_alloc_with(|v| v == "Jill", || "Jill".into());
+        assert!(allocated);
+
+        let (second, allocated) = arena.get_or_alloc_with(|v| v == "Jill", || "Jill".into());
+        assert!(!allocated);
+
+        assert!(first == second);
+        assert_eq!(arena.len(), 1);
+    }
+
+    #[test]
+    fn stable_id() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let id = julia.stable_id().unwrap();
+        assert_ne!(id, jane.stable_id().unwrap());
+
+        arena.swap_remove(&john);
+        arena.swap(&julia, &jane);
+
+        assert!(john.stable_id().is_none());
+        assert_eq!(julia.stable_id(), Some(id));
+
+        let (idx, value) = arena.get_by_stable_id(id).unwrap();
+        assert!(idx == julia);
+        assert_eq!(value, "Julia");
+
+        // Recycled handles get a fresh id
+        let jill = arena.alloc("Jill".into());
+        assert!(jill.stable_id().unwrap() > jake.stable_id().unwrap());
+        assert!(arena.get_by_stable_id(0).is_none());
+    }
+
+    #[test]
+    fn is_removed_and_is_live() {
+        let (mut arena, john, julia, _, _) = setup_arena();
+
+        assert!(john.is_live());
+        assert!(!john.is_removed());
+
+        arena.remove(&john);
+        arena.swap_remove(&julia);
+
+        assert!(john.is_removed());
+        assert!(!john.is_live());
+        assert!(julia.is_removed());
+    }
+
+    #[test]
+    fn ordering_snapshot_round_trip() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let snapshot = arena.ordering_snapshot();
+        assert_eq!(snapshot, vec![john.clone(), julia, jane, jake.clone()]);
+
+        arena.sort_by(|a, b| a.cmp(b));
+        assert_eq!(
+            arena.iter().collect::<Vec<_>>(),
+            vec!["Jake", "Jane", "John", "Julia"]
+        );
+        assert_eq!(jake.value(), Some(0));
+
+        arena.apply_ordering(&snapshot);
+        assert_eq!(
+            arena.iter().collect::<Vec<_>>(),
+            vec!["John", "Julia", "Jane", "Jake"]
+        );
+        assert_eq!(john.value(), Some(0));
+        assert_eq!(jake.value(), Some(3));
+    }
+
+    #[test]
+    fn insert_before_and_after() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let first = arena.insert_before(&john, "First".into()).unwrap();
+        let last = arena.insert_after(&jake, "Last".into()).unwrap();
+        let middle = arena.insert_after(&julia, "Middle".into()).unwrap();
+
+        assert_eq!(
+            arena.iter().collect::<Vec<_>>(),
+            vec!["First", "John", "Julia", "Middle", "Jane", "Jake", "Last"]
+        );
+
+        for (position, idx) in [&first, &john, &julia, &middle, &jane, &jake, &last]
+            .iter()
+            .enumerate()
+        {
+            assert_eq!(idx.value(), Some(position));
+        }
+
+        arena.remove(&jane);
+        assert!(arena.insert_before(&jane, "Never".into()).is_none());
+        assert!(arena.insert_after(&jane, "Never".into()).is_none());
+    }
+
+    #[test]
+    fn dropping_arena_should_remove_handles() {
+        let (john, weak) = {
+            let (arena, john, _, _, _) = setup_arena();
+            assert_eq!(john.value(), Some(0));
+            (john, arena.get_idx_at_index(1).unwrap().downgrade())
+        };
+
+        assert!(john.value().is_none());
+        assert!(john.is_removed());
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[test]
+    fn chunks() {
+        let mut arena = Arena::from_fn(10, |i| i);
+
+        let chunks: Vec<&[usize]> = arena.chunks(4).collect();
+        assert_eq!(
+            chunks,
+            vec![&[0, 1, 2, 3][..], &[4, 5, 6, 7][..], &[8, 9][..]]
+        );
+
+        for (i, chunk) in arena.chunks_mut(4).enumerate() {
+            for value in chunk {
+                *value = i;
+            }
+        }
+
+        assert_eq!(arena.to_vec(), vec![0, 0, 0, 0, 1, 1, 1, 1, 2, 2]);
+    }
+
+    #[test]
+    fn count() {
+        let (arena, _, _, _, _) = setup_arena();
+
+        assert_eq!(arena.count(|v| v.starts_with("Ja")), 2);
+        assert_eq!(arena.count(|v| v.is_empty()), 0);
+    }
+
+    #[test]
+    fn for_each_entry() {
+        let (mut arena, _, _, _, _) = setup_arena();
+
+        arena.for_each_entry_mut(|idx, value| value.push_str(&idx.value().unwrap().to_string()));
+
+        let mut pairs = Vec::new();
+        arena.for_each_entry(|idx, value| pairs.push((idx.value(), value.clone())));
+
+        assert_eq!(
+            pairs,
+            vec![
+                (Some(0), "John0".to_string()),
+                (Some(1), "Julia1".to_string()),
+                (Some(2), "Jane2".to_string()),
+                (Some(3), "Jake3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn swap_remove_every_position() {
+        for len in 1..=3 {
+            for position in 0..len {
+                let mut arena = Arena::new();
+                let handles: Vec<Idx> = (0..len).map(|value| arena.alloc(value)).collect();
+
+                assert_eq!(arena.swap_remove(&handles[position]), position);
+                assert!(handles[position].is_removed());
+                assert_eq!(arena.len(), len - 1);
+
+                let mut expected: Vec<usize> = (0..len).collect();
+                expected.swap_remove(position);
+                assert_eq!(arena.iter().copied().collect::<Vec<_>>(), expected);
+
+                for (value, handle) in handles.iter().enumerate() {
+                    if value != position {
+                        let index = handle.value().unwrap();
+                        assert_eq!(expected[index], value);
+                        assert_eq!(arena.get(handle), Some(&value));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn removed_slots() {
+        let (mut arena, john, _, jane, _) = setup_arena();
+        assert_eq!(arena.removed_slots().count(), 0);
+
+        arena.remove(john);
+        arena.swap_remove(jane);
+        arena.alloc("Jill".to_string());
+
+        assert_eq!(arena.removed_slots().count(), arena.removed_count());
+        assert_eq!(arena.removed_slots().next(), None);
+    }
+
+    #[test]
+    fn hash_arena() {
+        use std::collections::HashSet;
+
+        let (first, _, _, _, _) = setup_arena();
+        let (second, _, _, _, _) = setup_arena();
+        let (mut third, john, _, _, _) = setup_arena();
+        third.remove(john);
+
+        assert!(first == second);
+        assert!(first != third);
+
+        let mut set = HashSet::new();
+        assert!(set.insert(first));
+        assert!(!set.insert(second));
+        assert!(set.insert(third));
+        assert_eq!(set.len(), 2);
+    }
+
+    #[test]
+    fn snapshot_value() {
+        let (mut arena, john, _, jane, jake) = setup_arena();
+        let other = Arena::from(vec!["Jill".to_string()]);
+
+        arena.swap_remove(&john);
+
+        assert_eq!(arena.snapshot_value(&john), None);
+        assert_eq!(arena.snapshot_value(&jake), Some(0));
+        assert_eq!(arena.snapshot_value(&jane), jane.value());
+        assert_eq!(other.snapshot_value(&jake), None);
+    }
+
+    #[test]
+    fn get_at() {
+        let (mut arena, _, julia, _, jake) = setup_arena();
+
+        assert_eq!(arena.get_at(0).unwrap(), "John");
+        assert_eq!(arena.get_at(3).unwrap(), "Jake");
+        assert_eq!(arena.get_at(4), None);
+        assert_eq!(arena.get_at(usize::MAX), None);
+
+        arena.get_at_mut(1).unwrap().push_str(" Smith");
+        assert_eq!(arena.get(&julia).unwrap(), "Julia Smith");
+        assert_eq!(arena.get_at_mut(4), None);
+
+        arena.remove(julia);
+        assert_eq!(arena.get_at(2).unwrap(), "Jake");
+        assert_eq!(arena.get_at(jake.value().unwrap()).unwrap(), "Jake");
+        assert_eq!(arena.get_at(3), None);
+    }
+
+    #[test]
+    fn swap_at() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        arena.swap_at(0, 3);
+
+        assert_eq!(arena.get_at(0).unwrap(), "Jake");
+        assert_eq!(arena.get_at(3).unwrap(), "John");
+        assert_eq!(jake.value(), Some(0));
+        assert_eq!(john.value(), Some(3));
+
+        for idx in [&john, &julia, &jane, &jake].iter() {
+            assert_eq!(arena.get(*idx), arena.get_at(idx.value().unwrap()));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn swap_at_out_of_bounds() {
+        let (mut arena, _, _, _, _) = setup_arena();
+        arena.swap_at(0, 4);
+    }
+
+    #[test]
+    fn reverse() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        arena.reverse();
+
+        assert_eq!(
+            arena.iter().map(|name| name.as_str()).collect::<Vec<_>>(),
+            vec!["Jake", "Jane", "Julia", "John"]
+        );
+
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        assert_eq!(jake.value(), Some(0));
+        assert_eq!(john.value(), Some(3));
+    }
+
+    #[test]
+    fn clone_iterators() {
+        struct NotClone(&'static str);
+
+        let mut arena = Arena::new();
+        let john = arena.alloc(NotClone("John"));
+        arena.alloc(NotClone("Julia"));
+        arena.alloc(NotClone("Jane"));
+
+        let mut iter = arena.iter();
+        assert_eq!(iter.next().unwrap().0, "John");
+
+        let mut copy = iter.clone();
+        assert_eq!(copy.next().unwrap().0, "Julia");
+        assert_eq!(copy.next().unwrap().0, "Jane");
+        assert!(copy.next().is_none());
+
+        assert_eq!(iter.next().unwrap().0, "Julia");
+        assert_eq!(iter.next().unwrap().0, "Jane");
+        assert!(iter.next().is_none());
+
+        let entries = arena.entries();
+        let (first, _) = entries.clone().next().unwrap();
+        assert_eq!(first, john);
+        assert_eq!(entries.count(), 3);
+    }
+
+    #[test]
+    fn find_mut() {
+        let (mut arena, john, julia, _, _) = setup_arena();
+
+        let (idx, value) = arena.find_mut(|name| name.starts_with('J')).unwrap();
+        value.push_str(" Smith");
+
+        assert_eq!(idx, john);
+        assert_eq!(arena.get(&john).unwrap(), "John Smith");
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert!(arena.find_mut(|name| name.starts_with('X')).is_none());
+    }
+
+    #[test]
+    fn singleton() {
+        let (mut arena, root) = Arena::singleton("Root".to_string());
+
+        assert_eq!(arena.len(), 1);
+        assert_eq!(root.value(), Some(0));
+        assert_eq!(arena.get(&root).unwrap(), "Root");
+
+        let child = arena.alloc("Child".to_string());
+        assert_eq!(child.value(), Some(1));
+    }
+
+    #[test]
+    fn splice() {
+        fn names(arena: &Arena<String>) -> Vec<&str> {
+            arena.iter().map(|name| name.as_str()).collect()
+        }
+
+        // Shrinking
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        let removed = arena.splice(1..3, vec!["Jill".to_string()]);
+        assert_eq!(removed, vec!["Julia".to_string(), "Jane".to_string()]);
+        assert_eq!(names(&arena), vec!["John", "Jill", "Jake"]);
+        assert!(julia.is_removed());
+        assert!(jane.is_removed());
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        assert_eq!(jake.value(), Some(2));
+        assert_eq!(arena.get_idx_at_index(1).unwrap().value(), Some(1));
+
+        // Growing
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        let removed = arena.splice(
+            1..2,
+            vec!["Jill".to_string(), "Jack".to_string(), "Joe".to_string()],
+        );
+        assert_eq!(removed, vec!["Julia".to_string()]);
+        assert_eq!(
+            names(&arena),
+            vec!["John", "Jill", "Jack", "Joe", "Jane", "Jake"]
+        );
+        assert!(julia.is_removed());
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        assert_eq!(jane.value(), Some(4));
+
+        // Equal length, and inserting without removing
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        let removed = arena.splice(0..1, vec!["Jill".to_string()]);
+        assert_eq!(removed, vec!["John".to_string()]);
+        assert!(john.is_removed());
+        assert!(arena.splice(4..4, vec!["Joe".to_string()]).is_empty());
+        assert_eq!(names(&arena), vec!["Jill", "Julia", "Jane", "Jake", "Joe"]);
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+
+        for (index, idx) in arena.entries().map(|(idx, _)| idx).enumerate() {
+            assert_eq!(idx.value(), Some(index));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn splice_past_max_capacity() {
+        let mut arena = Arena::with_max_capacity(2);
+        arena.alloc(1);
+        arena.splice(0..1, vec![2, 3, 4]);
+    }
+
+    #[test]
+    fn arena_ids() {
+        let (mut first, john, _, _, _) = setup_arena();
+        let mut second: Arena<String> = vec!["Jill".to_string()].into();
+
+        assert_ne!(first.id(), second.id());
+        assert_eq!(john.arena_id(), first.id());
+        assert_eq!(second.get_idx_at_index(0).unwrap().arena_id(), second.id());
+
+        // Recycled handles keep the id of the arena they're recycled in
+        first.remove(john);
+        assert_eq!(first.alloc("Jack".to_string()).arena_id(), first.id());
+        assert_eq!(second.alloc("Joe".to_string()).arena_id(), second.id());
+    }
+
+    #[test]
+    fn is_sorted() {
+        let (mut arena, _, _, _, _) = setup_arena();
+        assert!(!arena.is_sorted());
+
+        arena.sort_by(|a, b| a.cmp(b));
+        assert!(arena.is_sorted());
+        assert!(arena.is_sorted_by(|a, b| a.partial_cmp(b)));
+        assert!(!arena.is_sorted_by(|a, b| b.partial_cmp(a)));
+        assert!(!arena.is_sorted_by(|_, _| None));
+
+        assert!(Arena::<u32>::new().is_sorted());
+        assert!(Arena::from(vec![1]).is_sorted());
+        assert!(Arena::from(vec![1, 1, 2]).is_sorted());
+    }
+
+    #[test]
+    fn filter_entries() {
+        let (arena, john, _, jane, jake) = setup_arena();
+
+        let entries: Vec<(Idx, &String)> = arena.filter_entries(|name| name.len() == 4).collect();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0], (john, &"John".to_string()));
+        assert_eq!(entries[1], (jane, &"Jane".to_string()));
+        assert_eq!(entries[2], (jake, &"Jake".to_string()));
+
+        assert_eq!(arena.filter_entries(|name| name.is_empty()).count(), 0);
+    }
+
+    #[test]
+    fn apply_ordering_in_place_matches_copying() {
+        let n = 50;
+        let mut permutations: Vec<Vec<usize>> = vec![
+            (0..n).collect(),
+            (0..n).rev().collect(),
+            (0..n).map(|i| (i + 7) % n).collect(),
+            (0..n).map(|i| i ^ 1).collect(),
+        ];
+
+        // A few shuffles from a small linear congruential generator
+        let mut seed: u64 = 42;
+        for _ in 0..4 {
+            let mut permutation: Vec<usize> = (0..n).collect();
+            for i in (1..n).rev() {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1);
+                permutation.swap(i, (seed >> 33) as usize % (i + 1));
+            }
+            permutations.push(permutation);
+        }
+
+        for permutation in permutations {
+            let mut in_place = Arena::from_fn(n, |i| i);
+            let mut copied = Arena::from_fn(n, |i| i);
+            let in_place_handles: Vec<Idx> = in_place.entries().map(|(idx, _)| idx).collect();
+            let copied_handles: Vec<Idx> = copied.entries().map(|(idx, _)| idx).collect();
+
+            let ordering: Vec<&Idx> = permutation.iter().map(|i| &in_place_handles[*i]).collect();
+            in_place.apply_ordering(&ordering);
+
+            let ordering: Vec<&Idx> = permutation.iter().map(|i| &copied_handles[*i]).collect();
+            copied.apply_ordering_by_copying(&ordering);
+
+            assert!(in_place == copied);
+            assert_eq!(in_place.iter().copied().collect::<Vec<_>>(), permutation);
+
+            for (a, b) in in_place_handles.iter().zip(copied_handles.iter()) {
+                assert_eq!(a.value(), b.value());
+                assert_eq!(in_place.get(a), copied.get(b));
+            }
+        }
+    }
+
+    #[test]
+    fn try_get() {
+        let (mut arena, john, julia, _, jake) = setup_arena();
+        let other = Arena::from(vec!["Jill".to_string()]);
+        let jill = other.get_idx_at_index(0).unwrap();
+
+        assert_eq!(arena.try_get(&julia).unwrap(), "Julia");
+        assert_eq!(other.try_get(&jill).unwrap(), "Jill");
+
+        arena.remove(&john);
+        assert_eq!(arena.try_get(&john), Err(ArenaError::Removed));
+        assert_eq!(arena.get(&john), None);
+
+        assert_eq!(arena.try_get(&jill), Err(ArenaError::ForeignArena));
+        assert_eq!(other.try_get(&julia), Err(ArenaError::ForeignArena));
+        assert_eq!(arena.get(&jill), None);
+
+        // Only reachable if the storage is out of sync with the handles
+        arena.values.pop();
+        assert_eq!(arena.try_get(&jake), Err(ArenaError::OutOfBounds));
+        arena.handles.pop();
+
+        assert_eq!(
+            ArenaError::ForeignArena.to_string(),
+            "handle belongs to another arena"
+        );
+    }
+
+    #[test]
+    fn reverse_entries() {
+        let (mut arena, john, _, _, jake) = setup_arena();
+
+        let forward: Vec<Idx> = arena.entries().map(|(idx, _)| idx).collect();
+        let mut backward: Vec<Idx> = arena.entries().rev().map(|(idx, _)| idx).collect();
+        backward.reverse();
+        assert_eq!(forward, backward);
+
+        let (last, value) = arena.entries().next_back().unwrap();
+        assert_eq!(last, jake);
+        assert_eq!(value, "Jake");
+
+        for (idx, value) in arena.entries_mut().rev() {
+            value.push_str(&idx.value().unwrap().to_string());
+        }
+        assert_eq!(arena.get(&john).unwrap(), "John0");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake3");
+
+        let mut entries = arena.entries_mut();
+        assert_eq!(entries.next().unwrap().0, john);
+        assert_eq!(entries.next_back().unwrap().0, jake);
+        assert_eq!(entries.count(), 2);
+    }
+
+    #[test]
+    fn shrink_pool() {
+        let mut arena = Arena::new();
+        for _ in 0..3 {
+            for i in 0..8 {
+                arena.alloc(i);
+            }
+            arena.clear_reuse();
+        }
+        assert_eq!(arena.pool.len(), 8);
+
+        // Without shrinking, the pooled inner is reused
+        let pooled = Arc::as_ptr(arena.pool.last().unwrap());
+        let reused = arena.alloc(0);
+        assert_eq!(Arc::as_ptr(&reused.inner), pooled);
+        assert_eq!(arena.pool.len(), 7);
+
+        arena.shrink_pool();
+        assert!(arena.pool.is_empty());
+        assert_eq!(arena.pool.capacity(), 0);
+
+        let fresh = arena.alloc(1);
+        assert!(arena.pool.is_empty());
+        assert_eq!(arena.get(&reused), Some(&0));
+        assert_eq!(arena.get(&fresh), Some(&1));
+    }
+
+    #[test]
+    fn remove_and_remove_fast_order() {
+        let (mut ordered, _, julia, _, jake) = setup_arena();
+        assert_eq!(ordered.remove(&julia), "Julia");
+        assert_eq!(
+            ordered.iter().map(|name| name.as_str()).collect::<Vec<_>>(),
+            vec!["John", "Jane", "Jake"]
+        );
+        assert_eq!(jake.value(), Some(2));
+
+        let (mut unordered, _, julia, _, jake) = setup_arena();
+        assert_eq!(unordered.remove_fast(&julia), "Julia");
+        assert_eq!(
+            unordered
+                .iter()
+                .map(|name| name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["John", "Jake", "Jane"]
+        );
+        assert_eq!(jake.value(), Some(1));
+
+        // Removing the last value is the same either way
+        let (mut ordered, _, _, _, last) = setup_arena();
+        let (mut unordered, _, _, _, unordered_last) = setup_arena();
+        ordered.remove(last);
+        unordered.remove_fast(unordered_last);
+        assert!(ordered == unordered);
+    }
+
+    #[test]
+    fn extend_from_slice() {
+        let (mut arena, john, _, _, jake) = setup_arena();
+        let extra = vec!["Jill".to_string(), "Jack".to_string(), "Joe".to_string()];
+
+        arena.extend_from_slice(&extra);
+
+        assert_eq!(arena.len(), 7);
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+
+        for (offset, name) in extra.iter().enumerate() {
+            let idx = arena.get_idx_at_index(4 + offset).unwrap();
+            assert_eq!(idx.value(), Some(4 + offset));
+            assert_eq!(arena.get(&idx), Some(name));
+        }
+    }
+
+    #[test]
+    fn from_iter_reserves_exact_lengths() {
+        let arena: Arena<usize> = (0..10_000).collect();
+        assert_eq!(arena.len(), 10_000);
+        assert_eq!(arena.capacity(), 10_000);
+
+        // Only an upper bound is known, so it isn't reserved up front
+        let arena: Arena<usize> = (0..100).filter(|i| i % 2 == 0).collect();
+        assert_eq!(arena.len(), 50);
+        assert!(arena.capacity() < 100);
+
+        let arena: Arena<usize> = (0..usize::MAX).take_while(|i| *i < 3).collect();
+        assert_eq!(arena.to_vec(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn get_pair_mut() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let (a, b) = arena.get_pair_mut(&jake, &julia).unwrap();
+        assert_eq!(a, "Jake");
+        assert_eq!(b, "Julia");
+        std::mem::swap(a, b);
+
+        assert_eq!(arena.get(&jake).unwrap(), "Julia");
+        assert_eq!(arena.get(&julia).unwrap(), "Jake");
+
+        let (a, b) = arena.get_pair_mut(&john, &jane).unwrap();
+        a.push('!');
+        b.push('?');
+        assert_eq!(arena.get(&john).unwrap(), "John!");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane?");
+
+        assert!(arena.get_pair_mut(&john, &john).is_none());
+        assert!(arena.get_pair_mut(&john, john.clone()).is_none());
+
+        arena.remove(&jane);
+        assert!(arena.get_pair_mut(&john, &jane).is_none());
+        assert!(arena.get_pair_mut(&jane, &john).is_none());
+    }
+
+    #[test]
+    fn try_for_each_entry() {
+        let (arena, _, _, jane, _) = setup_arena();
+
+        let mut visited = Vec::new();
+        let result = arena.try_for_each_entry(|idx, value| {
+            visited.push(value.clone());
+            if value == "Jane" {
+                Err(idx)
+            } else {
+                Ok(())
+            }
+        });
+
+        assert_eq!(result, Err(jane));
+        assert_eq!(visited, vec!["John", "Julia", "Jane"]);
+
+        let mut count = 0;
+        assert_eq!(
+            arena.try_for_each_entry(|_, _| {
+                count += 1;
+                Ok::<(), ()>(())
+            }),
+            Ok(())
+        );
+        assert_eq!(count, 4);
+    }
+
+    #[test]
+    fn snapshot() {
+        let (mut arena, _, _, jane, _) = setup_arena();
+
+        let mut seen = Vec::new();
+        for idx in arena.snapshot() {
+            match arena.get(&idx) {
+                Some(value) => seen.push(value.clone()),
+                None => seen.push("Removed".to_string()),
+            }
+
+            if seen.len() == 1 {
+                arena.remove(&jane);
+                arena.alloc("Jill".to_string());
+            }
+        }
+
+        assert_eq!(seen, vec!["John", "Julia", "Removed", "Jake"]);
+        assert_eq!(arena.len(), 4);
+    }
+
+    #[test]
+    fn try_remove() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        let other = Arena::from(vec!["Jill".to_string()]);
+        let jill = other.get_idx_at_index(0).unwrap();
+
+        assert_eq!(arena.try_remove(&julia), Ok("Julia".to_string()));
+        assert_eq!(jake.value(), Some(2));
+        assert_eq!(arena.try_remove(&julia), Err(ArenaError::Removed));
+        assert_eq!(arena.try_remove(&jill), Err(ArenaError::ForeignArena));
+
+        assert_eq!(arena.try_swap_remove(&john), Ok("John".to_string()));
+        assert_eq!(jake.value(), Some(0));
+        assert_eq!(jane.value(), Some(1));
+        assert_eq!(arena.try_swap_remove(&john), Err(ArenaError::Removed));
+        assert_eq!(arena.try_swap_remove(&jill), Err(ArenaError::ForeignArena));
+
+        assert_eq!(arena.len(), 2);
+        assert_eq!(other.len(), 1);
+
+        assert_eq!(
+            arena.try_apply_ordering(&[&jane]),
+            Err(OrderingError::WrongLength {
+                expected: 2,
+                found: 1
+            })
+        );
+        assert_eq!(arena.try_apply_ordering(&[&jane, &jake]), Ok(()));
+        assert_eq!(jane.value(), Some(0));
+    }
+
+    #[test]
+    #[should_panic]
+    fn remove_removed_panics() {
+        let (mut arena, john, _, _, _) = setup_arena();
+        arena.remove(&john);
+        arena.remove(&john);
+    }
+
+    #[test]
+    #[should_panic]
+    fn swap_remove_removed_panics() {
+        let (mut arena, john, _, _, _) = setup_arena();
+        arena.swap_remove(&john);
+        arena.swap_remove(&john);
+    }
+
+    #[test]
+    #[should_panic]
+    fn apply_invalid_ordering_panics() {
+        let (mut arena, john, _, _, _) = setup_arena();
+        arena.apply_ordering(&[john]);
+    }
+
+    #[test]
+    fn serialize_with_map() {
+        struct Edge {
+            from: u64,
+            to: u64,
+        }
+
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        arena.remove(&julia);
+        arena.swap(&john, &jake);
+
+        // References to values are stored as stable ids
+        let edges = [
+            Edge {
+                from: john.stable_id().unwrap(),
+                to: jane.stable_id().unwrap(),
+            },
+            Edge {
+                from: jake.stable_id().unwrap(),
+                to: john.stable_id().unwrap(),
+            },
+        ];
+
+        let (values, ids) = arena.serialize_with_map();
+        let values: Vec<String> = values.into_iter().cloned().collect();
+        assert_eq!(values, vec!["Jake", "Jane", "John"]);
+
+        let (mut restored, map) = Arena::deserialize_with_map(values, ids);
+
+        assert_eq!(restored.get(&map[&edges[0].from]).unwrap(), "John");
+        assert_eq!(restored.get(&map[&edges[0].to]).unwrap(), "Jane");
+        assert_eq!(restored.get(&map[&edges[1].from]).unwrap(), "Jake");
+        assert_eq!(restored.get(&map[&edges[1].to]).unwrap(), "John");
+
+        // Ids are kept, and new values don't collide with them
+        assert_eq!(map[&edges[0].from].stable_id(), john.stable_id());
+        let (_, found) = restored.get_by_stable_id(edges[1].from).unwrap();
+        assert_eq!(found, "Jake");
+        let jill = restored.alloc("Jill".to_string());
+        assert!(!map.contains_key(&jill.stable_id().unwrap()));
+    }
+
+    #[test]
+    #[should_panic]
+    fn deserialize_with_duplicate_ids() {
+        Arena::deserialize_with_map(vec![1, 2], vec![0, 0]);
+    }
+
+    #[test]
+    fn partition() {
+        let (arena, john, _, _, _) = setup_arena();
+
+        let (short, long) = arena.partition(|name| name.len() == 4);
+
+        assert_eq!(short.to_vec(), vec!["John", "Jane", "Jake"]);
+        assert!(john.is_removed());
+
+        let julia = long.get_idx_at_index(0).unwrap();
+        assert_eq!(long.get(&julia).unwrap(), "Julia");
+        assert_eq!(julia.arena_id(), long.id());
+        assert_eq!(long.len(), 1);
+    }
+
+    #[test]
+    fn partition_in_place() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        arena.alloc("Jennifer".to_string());
+
+        let mut long = arena.partition_in_place(|name| name.len() == 4);
+
+        assert_eq!(
+            arena.iter().map(|name| name.as_str()).collect::<Vec<_>>(),
+            vec!["John", "Jane", "Jake"]
+        );
+        assert_eq!(
+            long.iter().map(|name| name.as_str()).collect::<Vec<_>>(),
+            vec!["Julia", "Jennifer"]
+        );
+
+        // Kept handles still resolve in the original arena
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&jane).unwrap(), "Jane");
+        assert_eq!(jake.value(), Some(2));
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+
+        // Moved handles follow their values into the new arena
+        assert_eq!(julia.value(), Some(0));
+        assert_eq!(julia.arena_id(), long.id());
+        assert_eq!(arena.try_get(&julia), Err(ArenaError::ForeignArena));
+        assert_eq!(long.get(&julia).unwrap(), "Julia");
+        assert_eq!(long.try_get(&john), Err(ArenaError::ForeignArena));
+
+        let jill = long.alloc("Jill".to_string());
+        assert_ne!(jill.stable_id(), julia.stable_id());
+        assert_eq!(long.remove(&julia), "Julia");
+        assert_eq!(long.get(&jill).unwrap(), "Jill");
+    }
+
+    #[test]
+    fn intern() {
+        let (mut arena, _, julia, _, _) = setup_arena();
+
+        assert_eq!(arena.intern("Julia".to_string()), julia);
+        assert_eq!(arena.len(), 4);
+
+        let jill = arena.intern("Jill".to_string());
+        assert_eq!(arena.len(), 5);
+        assert_eq!(arena.intern("Jill".to_string()), jill);
+        assert_eq!(arena.len(), 5);
+        assert_eq!(arena.get(&jill).unwrap(), "Jill");
+    }
+
+    #[test]
+    fn secondary_map() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+        let mut ages = SecondaryMap::new();
+
+        assert_eq!(ages.insert(&john, 30), None);
+        assert_eq!(ages.insert(&julia, 25), None);
+        assert_eq!(ages.insert(&jane, 41), None);
+        assert_eq!(ages.insert(&john, 31), Some(30));
+        assert_eq!(ages.len(), 3);
+
+        assert_eq!(ages.get(&john), Some(&31));
+        assert_eq!(ages.get(&jake), None);
+        *ages.get_mut(&jane).unwrap() += 1;
+        assert_eq!(ages.get(&jane), Some(&42));
+
+        // Reordering the arena doesn't affect the map
+        arena.swap(&john, &jake);
+        assert_eq!(ages.get(&john), Some(&31));
+
+        arena.remove(&julia);
+        arena.swap_remove(&jane);
+        assert_eq!(ages.len(), 3);
+
+        arena.prune_secondary_map(&mut ages);
+        assert_eq!(ages.len(), 1);
+        assert!(ages.contains_key(&john));
+        assert!(!ages.contains_key(&julia));
+
+        let entries: Vec<(&Idx, &u32)> = ages.iter().collect();
+        assert_eq!(entries, vec![(&john, &31)]);
+
+        assert_eq!(ages.remove(&john), Some(31));
+        assert!(ages.is_empty());
+    }
+
+    #[test]
+    fn element_stride() {
+        let arena = Arena::<u64>::with_capacity(10);
+        assert_eq!(
+            arena.element_stride(),
+            std::mem::size_of::<u64>() + std::mem::size_of::<usize>()
+        );
+        assert_eq!(arena.value_capacity(), 10);
+        assert_eq!(arena.handle_capacity(), 10);
+        assert_eq!(arena.capacity(), 10);
+
+        let arena = Arena::<()>::with_capacity(10);
+        assert_eq!(arena.element_stride(), std::mem::size_of::<usize>());
+        assert_eq!(arena.value_capacity(), usize::MAX);
+        assert_eq!(arena.capacity(), 10);
+    }
+
+    #[test]
+    fn retain_reporting() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
+
+        let removed = arena.retain_reporting(|name| name == "Julia" || name == "Jake");
+
+        assert_eq!(removed, vec![john.clone(), jane.clone()]);
+        assert!(removed.iter().all(Idx::is_removed));
+        assert_eq!(julia.value(), Some(0));
+        assert_eq!(jake.value(), Some(1));
+        assert_eq!(arena.to_vec(), vec!["Julia", "Jake"]);
+    }
+
+    #[test]
+    fn with_full_capacity() {
+        let mut arena = Arena::with_full_capacity(16);
+        assert_eq!(arena.capacity(), 16);
+        assert_eq!(arena.pool.len(), 16);
+
+        let handles: Vec<Idx> = (0..16).map(|i| arena.alloc(i)).collect();
+        assert!(arena.pool.is_empty());
+        assert_eq!(arena.capacity(), 16);
+
+        for (i, idx) in handles.iter().enumerate() {
+            assert_eq!(idx.value(), Some(i));
+            assert_eq!(idx.stable_id(), Some(i as u64));
+            assert_eq!(idx.arena_id(), arena.id());
+            assert_eq!(arena.get(idx), Some(&i));
+        }
+    }
+
+    #[test]
+    fn try_alloc_with_idx() {
+        let (mut arena, _, _, _, jake) = setup_arena();
+        let mut leaked = None;
+
+        let result = arena.try_alloc_with_idx(|idx| {
+            leaked = Some(idx);
+            Err("Nope")
+        });
+
+        assert_eq!(result, Err("Nope"));
+        assert_eq!(arena.len(), 4);
+
+        let leaked = leaked.unwrap();
+        assert!(leaked.is_removed());
+        assert!(!arena.contains(&leaked));
+        assert_eq!(arena.get(&leaked), None);
+
+        // Nothing drifted, so the next value lands where the failed one would have
+        let jill = arena
+            .try_alloc_with_idx(|idx| Ok::<_, ()>(format!("Jill {}", idx.value().unwrap())))
+            .unwrap();
+        assert_eq!(jill.value(), Some(4));
+        assert_eq!(jill.stable_id(), Some(4));
+        assert_ne!(jill, leaked);
+        assert_eq!(arena.get(&jill).unwrap(), "Jill 4");
+        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+    }
+
+    #[test]
+    fn permute() {
         let (mut arena, john, julia, jane, jake) = setup_arena();
 
-        assert_eq!(arena.get_index(0), "John");
-        assert_eq!(arena.get_index(1), "Julia");
-        assert_eq!(arena.get_index(2), "Jane");
-        assert_eq!(arena.get_index(3), "Jake");
+        arena.permute(&[2, 0, 3, 1]);
 
-        assert_eq!(arena.get(john).unwrap(), "John");
-        assert_eq!(arena.get(julia).unwrap(), "Julia");
-        assert_eq!(arena.get(jane).unwrap(), "Jane");
-        assert_eq!(arena.get(jake).unwrap(), "Jake");
+        assert_eq!(
+            arena.iter().map(|name| name.as_str()).collect::<Vec<_>>(),
+            vec!["Jane", "John", "Jake", "Julia"]
+        );
+        assert_eq!(jane.value(), Some(0));
+        assert_eq!(john.value(), Some(1));
+        assert_eq!(jake.value(), Some(2));
+        assert_eq!(julia.value(), Some(3));
+
+        arena.permute(&[0, 1, 2, 3]);
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
     }
 
     #[test]
-    fn arena_length() {
+    #[should_panic]
+    fn permute_with_duplicate() {
         let (mut arena, _, _, _, _) = setup_arena();
-        assert_eq!(arena.len(), 4);
-        arena.alloc("Wow".into());
-        assert_eq!(arena.len(), 5);
+        arena.permute(&[0, 1, 1, 3]);
     }
 
     #[test]
-    fn swap_indexes() {
+    #[should_panic]
+    fn permute_out_of_range() {
+        let (mut arena, _, _, _, _) = setup_arena();
+        arena.permute(&[0, 1, 2, 4]);
+    }
+
+    #[test]
+    fn reindex_observer() {
+        use std::sync::Mutex;
+
         let (mut arena, john, julia, jane, jake) = setup_arena();
+        let events = Arc::new(Mutex::new(Vec::new()));
 
-        assert_eq!(arena.get_index(0), "John");
-        assert_eq!(arena.get_index(1), "Julia");
-        assert_eq!(arena.get_index(2), "Jane");
-        assert_eq!(arena.get_index(3), "Jake");
+        let recorded = Arc::clone(&events);
+        arena.set_reindex_observer(Box::new(move |idx, index| {
+            recorded.lock().unwrap().push((idx.clone(), index));
+        }));
 
-        assert_eq!(arena.get(&john).unwrap(), "John");
-        assert_eq!(arena.get(&julia).unwrap(), "Julia");
-        assert_eq!(arena.get(&jane).unwrap(), "Jane");
-        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        let take = || std::mem::take(&mut *events.lock().unwrap());
 
-        arena.swap(&john, &julia);
-        arena.swap(&jane, &jake);
+        arena.remove(&john);
+        assert_eq!(
+            take(),
+            vec![(julia.clone(), 0), (jane.clone(), 1), (jake.clone(), 2)]
+        );
 
-        assert_eq!(arena.get_index(0), "Julia");
-        assert_eq!(arena.get_index(1), "John");
-        assert_eq!(arena.get_index(2), "Jake");
-        assert_eq!(arena.get_index(3), "Jane");
+        // Allocating at the end and removing the last value don't move anything
+        let jill = arena.alloc("Jill".to_string());
+        arena.remove(&jill);
+        assert!(take().is_empty());
 
-        assert_eq!(arena.get(&john).unwrap(), "John");
-        assert_eq!(arena.get(&julia).unwrap(), "Julia");
-        assert_eq!(arena.get(&jane).unwrap(), "Jane");
-        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        arena.swap_remove(&julia);
+        assert_eq!(take(), vec![(jake.clone(), 0)]);
+
+        arena.swap(&jake, &jane);
+        assert_eq!(take(), vec![(jane.clone(), 0), (jake.clone(), 1)]);
+
+        // Only the values that actually move are reported
+        arena.apply_ordering(&[&jane, &jake]);
+        assert!(take().is_empty());
+        arena.reverse();
+        assert_eq!(take(), vec![(jake.clone(), 0), (jane.clone(), 1)]);
+
+        arena.clear_reindex_observer();
+        arena.reverse();
+        assert!(take().is_empty());
+        assert_eq!(jane.value(), Some(0));
     }
 
     #[test]
-    fn remove() {
-        let (mut arena, john, julia, jane, jake) = setup_arena();
+    fn into_entries() {
+        let (arena, john, julia, jane, jake) = setup_arena();
+
+        let mut entries = arena.into_entries();
+        let (first, value) = entries.next().unwrap();
+        assert_eq!(first, john);
+        assert_eq!(value, "John");
+        assert!(john.is_removed());
+        assert_eq!(julia.value(), Some(1));
+
+        let map: HashMap<Idx, String> = entries.collect();
+        assert_eq!(map.len(), 3);
+        assert_eq!(map[&julia], "Julia");
+        assert_eq!(map[&jane], "Jane");
+        assert_eq!(map[&jake], "Jake");
+        assert!(julia.is_removed());
+
+        // Dropping the iterator early still marks the rest as removed
+        let (arena, _, julia, _, _) = setup_arena();
+        arena.into_entries().next();
+        assert!(julia.is_removed());
+    }
 
-        assert_eq!(arena.len(), 4);
+    #[test]
+    fn insert_sorted() {
+        let mut arena = Arena::new();
+        let values = [5, 1, 4, 1, 3, 9, 0];
 
-        arena.remove(&john);
+        let handles: Vec<Idx> = values
+            .iter()
+            .map(|value| arena.insert_sorted(*value))
+            .collect();
 
-        assert!(arena.get(&john).is_none());
-        assert_eq!(arena.get(&julia).unwrap(), "Julia");
-        assert_eq!(arena.get(&jane).unwrap(), "Jane");
-        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        assert!(arena.is_sorted());
+        assert_eq!(
+            arena.iter().copied().collect::<Vec<_>>(),
+            vec![0, 1, 1, 3, 4, 5, 9]
+        );
 
-        assert_eq!(arena.get_index(0), "Julia");
-        assert_eq!(arena.get_index(1), "Jane");
-        assert_eq!(arena.get_index(2), "Jake");
+        for (idx, value) in handles.iter().zip(values.iter()) {
+            assert_eq!(arena.get(idx), Some(value));
+        }
+
+        // The second 1 went after the first
+        assert!(handles[1].value() < handles[3].value());
     }
 
     #[test]
-    fn swap_remove() {
-        let (mut arena, john, julia, jane, jake) = setup_arena();
+    fn raw_entries() {
+        let (arena, _, _, _, _) = setup_arena();
 
-        assert_eq!(arena.len(), 4);
+        let raw: Vec<(usize, &String)> = arena.raw_entries().collect();
+        let entries: Vec<(usize, &String)> = arena
+            .entries()
+            .map(|(idx, value)| (idx.value().unwrap(), value))
+            .collect();
 
-        arena.swap_remove(&john);
+        assert_eq!(raw, entries);
+        assert_eq!(raw[3], (3, &"Jake".to_string()));
+    }
 
-        assert!(arena.get(&john).is_none());
-        assert_eq!(arena.get(&julia).unwrap(), "Julia");
-        assert_eq!(arena.get(&jane).unwrap(), "Jane");
-        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+    #[test]
+    fn entries_ref() {
+        let (mut arena, john, julia, _, jake) = setup_arena();
+        arena.remove(&julia);
+
+        let kept: Vec<Idx> = arena
+            .entries_ref()
+            .filter(|(_, value)| value.starts_with("Ja"))
+            .map(|(idx, _)| idx.clone())
+            .collect();
+        assert_eq!(kept.len(), 2);
+        assert_eq!(kept[1], jake);
+
+        let (first, value) = arena.entries_ref().next().unwrap();
+        assert_eq!(*first, john);
+        assert_eq!(first.value(), Some(0));
+        assert_eq!(value, "John");
+
+        let (last, _) = arena.entries_ref().next_back().unwrap();
+        assert_eq!(last.value(), Some(2));
+
+        // Borrowing the handles doesn't add references
+        let strong = Arc::strong_count(&jake.inner);
+        assert_eq!(arena.entries_ref().count(), 3);
+        let borrowed: Vec<&Idx> = arena.entries_ref().map(|(idx, _)| idx).collect();
+        assert_eq!(Arc::strong_count(&jake.inner), strong);
+        assert_eq!(*borrowed[2], jake);
+    }
 
-        assert_eq!(arena.get_index(0), "Jake");
-        assert_eq!(arena.get_index(1), "Julia");
-        assert_eq!(arena.get_index(2), "Jane");
+    #[test]
+    fn collect_entries() {
+        let (arena, john, julia, jane, jake) = setup_arena();
+
+        let arena: Arena<String> = arena
+            .into_entries()
+            .filter(|(_, name)| name != "Julia")
+            .map(|(idx, name)| (idx, name.to_uppercase()))
+            .collect();
+
+        assert_eq!(arena.get(&john).unwrap(), "JOHN");
+        assert_eq!(arena.get(&jane).unwrap(), "JANE");
+        assert_eq!(arena.get(&jake).unwrap(), "JAKE");
+        assert_eq!(jake.value(), Some(2));
+        assert_eq!(jake.arena_id(), arena.id());
+        assert!(julia.is_removed());
+        assert!(!arena.contains(&julia));
+
+        let mut arena = arena;
+        let jill = arena.alloc("JILL".to_string());
+        assert!(jill.stable_id() > jake.stable_id());
     }
 
     #[test]
-    fn remove_should_remove_last_value() {
+    #[should_panic]
+    fn collect_live_handles() {
+        let (arena, _, _, _, _) = setup_arena();
+        let _copy: Arena<String> = arena
+            .entries()
+            .map(|(idx, name)| (idx, name.clone()))
+            .collect();
+    }
+
+    #[test]
+    fn flush_removals() {
         let (mut arena, john, julia, jane, jake) = setup_arena();
 
+        let handles: Vec<Idx> = arena.entries().map(|(idx, _)| idx).collect();
+        for idx in handles.iter() {
+            if arena.get(idx).unwrap().ends_with('e') {
+                arena.mark_remove(idx);
+            }
+        }
+
+        // Nothing has moved yet
+        arena.mark_remove(&jake);
         assert_eq!(arena.len(), 4);
+        assert_eq!(jake.value(), Some(3));
 
-        arena.swap_remove(&jake);
-        arena.remove(&jane);
-        arena.swap_remove(&julia);
-        arena.remove(&john);
+        let removed = arena.flush_removals();
+        assert_eq!(removed, vec!["Jane", "Jake"]);
+        assert!(jane.is_removed());
+        assert!(jake.is_removed());
+        assert_eq!(julia.value(), Some(1));
+        assert_eq!(arena.get(&john).unwrap(), "John");
+        assert_eq!(arena.get(&julia).unwrap(), "Julia");
 
-        assert_eq!(arena.len(), 0);
+        // Already removed values are skipped, and the queue is empty after a flush
+        arena.mark_remove(&jane);
+        assert!(arena.flush_removals().is_empty());
+        assert!(arena.flush_removals().is_empty());
+        assert_eq!(arena.len(), 2);
     }
 
     #[test]
-    fn convert_to_vec() {
-        let (arena, _, _, _, _) = setup_arena();
-        assert_eq!(arena.to_vec(), vec!["John", "Julia", "Jane", "Jake"]);
+    fn handle_pool() {
+        let mut pool = HandlePool::new();
+
+        let a = pool.alloc();
+        let b = pool.alloc();
+        let c = pool.alloc();
+        assert_eq!(pool.len(), 3);
+        assert_ne!(a, b);
+
+        assert!(pool.free(&a));
+        assert!(!pool.free(&a));
+        assert!(a.is_removed());
+        assert!(!pool.contains(&a));
+        assert!(pool.contains(&b) && pool.contains(&c));
+        assert_eq!(pool.len(), 2);
+
+        let live: Vec<&Idx> = pool.iter().collect();
+        assert_eq!(live.len(), 2);
+        assert!(live.contains(&&b) && live.contains(&&c));
+
+        // Freed handles that are still referenced aren't reused
+        let d = pool.alloc();
+        assert_ne!(d, a);
+        assert!(a.is_removed());
+
+        pool.clear();
+        assert!(pool.is_empty());
+        assert!(b.is_removed() && c.is_removed() && d.is_removed());
+
+        let mut pool = HandlePool::with_capacity(8);
+        let handles: Vec<Idx> = (0..8).map(|_| pool.alloc()).collect();
+        assert!(handles.iter().all(|idx| pool.contains(idx)));
     }
 
     #[test]
-    fn index_should_be_hashable() {
-        let (mut arena, john, julia, jane, jake) = setup_arena();
-
-        let mut seen = std::collections::HashSet::<Idx>::new();
+    fn locate() {
+        let (mut arena, john, julia, _, jake) = setup_arena();
 
-        seen.insert(jake.clone());
-        assert!(seen.contains(&jake));
-        assert_eq!(jake.value().unwrap(), 3);
+        arena.remove(&john);
 
-        arena.remove(john);
-        arena.remove(julia);
-        arena.remove(jane);
+        let (position, value) = arena.locate(&jake).unwrap();
+        assert_eq!(position, jake.value().unwrap());
+        assert_eq!(position, 2);
+        assert_eq!(value, "Jake");
 
-        assert_eq!(jake.value().unwrap(), 0);
-        assert!(seen.contains(&jake));
+        assert_eq!(arena.locate(&julia).unwrap().0, julia.value().unwrap());
+        assert_eq!(arena.locate(&john), None);
     }
 
     #[test]
-    fn cloned_index_should_equal() {
-        let (_, john, _, _, _) = setup_arena();
+    fn get_by_value() {
+        let (arena, _, julia, _, _) = setup_arena();
 
-        let a = john.clone();
-        let b = john;
+        assert_eq!(arena.get_by_value("Julia"), Some(julia));
+        assert_eq!(arena.get_by_value("Jill"), None);
 
-        assert!(a == b);
+        let numbers: Arena<Vec<u32>> = vec![vec![1, 2], vec![3]].into();
+        let three = numbers.get_by_value(&[3][..]).unwrap();
+        assert_eq!(three.value(), Some(1));
     }
 
     #[test]
-    fn apply_ordering() {
+    fn split_storage_stays_pinned() {
         let (mut arena, john, julia, jane, jake) = setup_arena();
 
-        let ordering = vec![&jake, &julia, &john, &jane];
-
-        assert_eq!(arena.get_index(0), "John");
-        assert_eq!(arena.get_index(1), "Julia");
-        assert_eq!(arena.get_index(2), "Jane");
-        assert_eq!(arena.get_index(3), "Jake");
+        let selected = {
+            let (j, mut split) = arena.split_at_with_capacity(&john, 2).unwrap();
 
-        arena.apply_ordering(&ordering);
+            split.alloc("Jill".to_string());
+            split.alloc("Jack".to_string());
+            split.remove(&julia);
+            split.swap_remove(&jane);
+            assert!(split.swap_remove(&jake).is_some());
 
-        assert_eq!(arena.get_index(0), "Jake");
-        assert_eq!(arena.get_index(1), "Julia");
-        assert_eq!(arena.get_index(2), "John");
-        assert_eq!(arena.get_index(3), "Jane");
+            j.push('!');
+            j as *const String
+        };
 
-        assert_eq!(arena.get(&john).unwrap(), "John");
-        assert_eq!(arena.get(&julia).unwrap(), "Julia");
-        assert_eq!(arena.get(&jane).unwrap(), "Jane");
-        assert_eq!(arena.get(&jake).unwrap(), "Jake");
+        // The selected value never moved, so the reference handed out by the split stayed valid
+        assert!(std::ptr::eq(selected, arena.get(&john).unwrap()));
+        assert_eq!(arena.get(&john).unwrap(), "John!");
+        assert_eq!(arena.len(), 3);
     }
 
     #[test]
-    fn position() {
-        let (arena, _, julia, _, _) = setup_arena();
+    fn alloc_within_capacity() {
+        let mut arena = Arena::with_capacity(4);
+        let capacity = arena.capacity();
+        let values = arena.values.as_ptr();
 
-        let j = arena.position(|v| v == "Julia").unwrap();
+        for i in 0..capacity {
+            assert!(arena.alloc_within_capacity(i).is_ok());
+        }
 
-        assert!(j == julia);
+        assert_eq!(arena.alloc_within_capacity(capacity), Err(capacity));
+        assert_eq!(arena.len(), capacity);
+        assert_eq!(arena.capacity(), capacity);
+        assert!(std::ptr::eq(values, arena.values.as_ptr()));
     }
 
     #[test]
-    fn truncate() {
-        let (mut arena, _, _, _, _) = setup_arena();
-        arena.truncate(0);
-        assert_eq!(arena.to_vec(), Vec::<String>::new());
-    }
+    fn handle_order_round_trips() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
 
-    #[test]
-    fn retain() {
-        let (mut arena, _, _, _, _) = setup_arena();
+        let order = arena.handle_order();
+        assert_eq!(order, vec![john.clone(), julia, jane, jake.clone()]);
 
-        arena.retain(|v| v == "Julia" || v == "Jane");
+        let moved = Arc::new(AtomicUsize::new(0));
+        let counter = moved.clone();
+        arena.set_reindex_observer(Box::new(move |_, _| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        }));
 
-        assert_eq!(arena.to_vec(), vec!["Julia", "Jane"]);
+        arena.apply_ordering(&order);
+
+        assert_eq!(moved.load(Ordering::SeqCst), 0);
+        assert_eq!(arena.handle_order(), order);
+        assert_eq!(john.value(), Some(0));
+        assert_eq!(jake.value(), Some(3));
+        assert_eq!(arena.to_vec(), vec!["John", "Julia", "Jane", "Jake"]);
     }
 
     #[test]
-    fn mut_iter() {
-        let (mut arena, _, _, _, _) = setup_arena();
+    fn remove_at() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
 
-        for val in arena.iter_mut() {
-            *val = "Wow".into();
-        }
+        assert_eq!(arena.remove_at(1), Some("Julia".to_string()));
+        assert!(julia.is_removed());
+        assert_eq!(john.value(), Some(0));
+        assert_eq!(jane.value(), Some(1));
+        assert_eq!(jake.value(), Some(2));
 
-        assert_eq!(arena.to_vec(), vec!["Wow"; 4])
+        assert_eq!(arena.remove_at(3), None);
+        assert_eq!(arena.len(), 3);
     }
 
     #[test]
-    fn iter() {
-        let (arena, _, _, _, _) = setup_arena();
+    fn swap_remove_at() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
 
-        let names = vec!["John", "Julia", "Jane", "Jake"];
+        assert_eq!(arena.swap_remove_at(0), Some("John".to_string()));
+        assert!(john.is_removed());
+        assert_eq!(jake.value(), Some(0));
+        assert_eq!(julia.value(), Some(1));
+        assert_eq!(jane.value(), Some(2));
 
-        for (a, b) in arena.iter().zip(names.iter()) {
-            assert_eq!(a, b);
-        }
+        assert_eq!(arena.swap_remove_at(3), None);
+        assert_eq!(arena.len(), 3);
     }
 
     #[test]
-    fn turn_iterator_into_vector() {
-        let names = vec!["John", "Julia", "Jane", "Jake"];
-        let other_names = vec!["John", "Julia", "Jane", "Jake"];
+    fn into_vec_keeping_handles() {
+        use std::collections::HashSet;
 
-        let arena = names.into_iter().collect::<Arena<_>>();
+        let (arena, john, julia, jane, jake) = setup_arena();
 
-        for (a, b) in arena.iter().zip(other_names.iter()) {
-            assert_eq!(a, b);
+        let (values, handles) = arena.into_vec_keeping_handles();
+        assert_eq!(handles, vec![john.clone(), julia, jane, jake]);
+
+        let mut named_j = HashSet::new();
+        for (value, handle) in values.iter().zip(&handles) {
+            assert!(handle.value().is_none());
+            if value.starts_with("Ja") {
+                named_j.insert(handle.clone());
+            }
         }
+
+        assert_eq!(named_j.len(), 2);
+        assert!(named_j.contains(&handles[2]));
+        assert!(!named_j.contains(&john));
     }
 
     #[test]
-    fn get_mut() {
-        let (mut arena, john, _, _, _) = setup_arena();
-        *(arena.get_mut(&john).unwrap()) = "Not John".into();
-        assert_eq!(arena.get(&john).unwrap(), "Not John");
+    fn index_by_position() {
+        let (mut arena, _, julia, _, _) = setup_arena();
+
+        assert_eq!(arena[0], "John");
+        arena[1].push('!');
+        assert_eq!(arena.get(&julia).unwrap(), "Julia!");
     }
 
-    struct Node {
-        id: Idx,
+    #[test]
+    #[should_panic]
+    fn index_by_position_should_panic_out_of_bounds() {
+        let (arena, _, _, _, _) = setup_arena();
+        let _ = &arena[4];
     }
 
     #[test]
-    fn alloc_with_idx() {
-        let mut arena = Arena::new();
-        let idx = arena.alloc_with_idx(|id| Node { id });
-        assert_eq!(arena.get(&idx).unwrap().id.value(), idx.value());
+    fn split_should_reject_foreign_handles() {
+        let (mut arena, john, _, _, _) = setup_arena();
+        let (_other, foreign, _, _, _) = setup_arena();
+
+        let (selected, mut split) = arena.split_at(&john).unwrap();
+
+        assert!(split.get(&foreign).is_none());
+        assert!(split.get_mut(&foreign).is_none());
+        assert!(split.remove(&foreign).is_none());
+        assert!(split.swap_remove(&foreign).is_none());
+
+        selected.push('!');
+        assert_eq!(arena.get(&john).unwrap(), "John!");
+        assert_eq!(arena.len(), 4);
     }
 
     #[test]
-    fn split_at() {
-        let (mut arena, john, julia, jane, jake) = setup_arena();
+    fn split_many_should_reject_foreign_handles() {
+        let (mut arena, _, julia, _, _) = setup_arena();
+        let (_other, _, foreign, _, _) = setup_arena();
 
-        let (j, mut arena) = arena.split_at(&julia).unwrap();
+        assert!(arena.split_many([&foreign]).is_none());
 
-        assert_eq!(j, "Julia");
-        assert!(arena.get_mut(john).is_some());
-        assert!(arena.get_mut(jane).is_some());
-        assert!(arena.get_mut(jake).is_some());
+        let ([selected], mut view) = arena.split_many([&julia]).unwrap();
+        assert!(view.get_mut(&julia).is_none());
+        selected.push('!');
+    }
 
-        assert!(arena.get_mut(julia).is_none());
+    #[test]
+    fn collecting_handles_should_keep_stable_ids_unique() {
+        let (first, john, julia, _, _) = setup_arena();
+        let (second, other_john, _, _, _) = setup_arena();
+        assert_eq!(john.stable_id(), other_john.stable_id());
+
+        let arena: Arena<String> = first.into_entries().chain(second.into_entries()).collect();
+        assert_eq!(arena.len(), 8);
+
+        let mut ids: Vec<u64> = arena
+            .handle_order()
+            .iter()
+            .map(|idx| idx.stable_id().unwrap())
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        assert_eq!(ids.len(), 8);
+
+        assert_eq!(john.stable_id(), Some(0));
+        assert_eq!(julia.stable_id(), Some(1));
+        assert_eq!(arena.get_by_stable_id(0).unwrap().0, john);
+        assert_eq!(arena.get(&other_john).unwrap(), "John");
     }
 
     #[test]
-    fn debug_printing() {
-        let (mut arena, john, _, _, _) = setup_arena();
+    fn lookups_should_agree_on_foreign_handles() {
+        let (mut arena, _, _, _, _) = setup_arena();
+        let (_other, foreign, _, _, _) = setup_arena();
 
-        assert_eq!(format!("{:?}", john), "Idx ( 0 )");
+        assert!(!arena.contains(&foreign));
+        assert!(arena.get(&foreign).is_none());
+        assert!(arena.get_mut(&foreign).is_none());
+        assert!(arena.get_disjoint_mut(&[&foreign]).is_none());
+        assert!(arena.split_at(&foreign).is_none());
 
-        arena.swap_remove(&john);
+        // The removers panic rather than removing whatever is at the foreign handle's position
+        let (mut bigger, _, _, _, _) = setup_arena();
+        let far = bigger.alloc("Jill".to_string());
+
+        fn panics<F: FnOnce()>(func: F) -> bool {
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(func)).is_err()
+        }
+
+        for handle in [&foreign, &far] {
+            assert!(panics(|| {
+                arena.remove(handle);
+            }));
+            assert!(panics(|| {
+                arena.swap_remove(handle);
+            }));
+            assert!(panics(|| {
+                arena.swap_remove_returning(handle);
+            }));
+            assert!(panics(|| {
+                arena.remove_fast(handle);
+            }));
+
+            assert!(handle.is_live());
+        }
 
-        assert_eq!(format!("{:?}", john), "Removed Idx ( 0 )");
+        assert_eq!(arena.len(), 4);
+        assert!(arena.handle_order().iter().all(|idx| idx.is_live()));
     }
 
     #[test]
-    fn compare_should_work_for_idx() {
-        let mut arena = Arena::new();
-        let left = arena.alloc(1);
-        let right = left.clone();
+    fn remove_all_should_notify_reindex_observer() {
+        let (mut arena, john, julia, jane, jake) = setup_arena();
 
-        assert!(left.borrow() == right.borrow());
+        let moved = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let observed = moved.clone();
+        arena.set_reindex_observer(Box::new(move |idx, index| {
+            observed.lock().unwrap().push((idx.clone(), index));
+        }));
+
+        arena.remove_all(&[&julia]);
+
+        assert_eq!(
+            *moved.lock().unwrap(),
+            vec![(jane.clone(), 1), (jake.clone(), 2)]
+        );
+        assert_eq!(john.value(), Some(0));
+        assert_eq!(jane.value(), Some(1));
+        assert_eq!(jake.value(), Some(2));
     }
 
     #[test]
-    fn splitting_should_be_safe() {
-        // A bug described here (https://github.com/bennetthardwick/nano-arena/issues/1) meant that
-        // multiple mutable references could be handed out.
+    fn arena_should_be_send_and_sync() {
+        fn assert_send_sync<T: Send + Sync>() {}
 
-        struct ToggleIdx {
-            first: Idx,
-            second: Idx,
-            state: Cell<bool>,
-        }
+        // Holds even with a reindex observer set, since observers have to be `Send + Sync`
+        assert_send_sync::<Arena<String>>();
+    }
 
-        impl Borrow<Idx> for ToggleIdx {
-            fn borrow(&self) -> &Idx {
-                self.state.set(!self.state.get());
+    #[test]
+    fn split_can_move_to_another_thread() {
+        let (mut arena, john, julia, _, _) = setup_arena();
 
-                if self.state.get() {
-                    &self.first
-                } else {
-                    &self.second
-                }
-            }
-        }
+        let (selected, mut split) = arena.split_at(&john).unwrap();
+        std::thread::scope(|scope| {
+            scope.spawn(move || split.get_mut(&julia).unwrap().push('!'));
+        });
+        selected.push('!');
 
-        let mut arena = Arena::new();
+        assert_eq!(arena.to_vec(), vec!["John!", "Julia!", "Jane", "Jake"]);
+    }
 
+    #[test]
+    fn recycled_handles_should_belong_to_their_arena() {
+        let mut arena = Arena::new();
         let first = arena.alloc(1);
-        let second = arena.alloc(2);
+        arena.swap_remove(&first);
 
-        let toggle_idx = ToggleIdx {
-            first: first.clone(),
-            second: second.clone(),
-            state: Cell::new(false),
-        };
+        // Collecting the removed handle retags it while its inner is still in the pool
+        let other: Arena<i32> = vec![(first, 5)].into_iter().collect();
+        drop(other);
 
-        let (first_mut_ref, mut split_arena) = arena.split_at(toggle_idx).unwrap();
-        let second_mut_ref = split_arena.get_mut(&first);
+        let second = arena.alloc(2);
+        assert_eq!(second.arena_id(), arena.id());
+        assert!(arena.contains(&second));
+        assert_eq!(arena.get(&second), Some(&2));
+        assert_eq!(arena.try_get(&second), Ok(&2));
+    }
 
-        drop(first_mut_ref);
-        assert!(second_mut_ref.is_none());
+    #[test]
+    #[should_panic(expected = "stable id of u64::MAX")]
+    fn deserialize_should_reject_the_last_stable_id() {
+        Arena::deserialize_with_map(vec![1], vec![u64::MAX]);
+    }
+
+    #[test]
+    #[should_panic(expected = "more values than there are stable ids")]
+    fn alloc_should_panic_past_the_last_stable_id() {
+        let (mut arena, _) = Arena::deserialize_with_map(vec![1], vec![u64::MAX - 1]);
+        arena.alloc(2);
     }
 }
@@ -0,0 +1,111 @@
+use super::{Arena, Idx};
+
+/// A single mutation of an [`Arena`], for driving an arena from a fuzzer or a property test.
+///
+/// Positions are taken modulo the length of the arena, so any operation can be applied to any
+/// non-empty arena. Operations other than `Alloc` do nothing to an empty arena.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Operation<T> {
+    Alloc(T),
+    Remove(usize),
+    SwapRemove(usize),
+    Swap(usize, usize),
+    /// Reorders the arena by sorting the current positions by these keys. Missing keys are zero.
+    ApplyOrdering(Vec<usize>),
+}
+
+// The positions of a `len` element arena, stably sorted by `keys`
+fn ordering(keys: &[usize], len: usize) -> Vec<usize> {
+    let mut positions: Vec<usize> = (0..len).collect();
+    positions.sort_by_key(|position| keys.get(*position).copied().unwrap_or(0));
+    positions
+}
+
+impl<T> Operation<T> {
+    /// Applies the operation to `arena`, returning the handle if a value was allocated.
+    pub fn apply(self, arena: &mut Arena<T>) -> Option<Idx> {
+        let len = arena.len();
+
+        if let Operation::Alloc(value) = self {
+            return Some(arena.alloc(value));
+        }
+
+        if len == 0 {
+            return None;
+        }
+
+        match self {
+            Operation::Alloc(_) => unreachable!(),
+            Operation::Remove(position) => {
+                arena.remove_index(position % len);
+            }
+            Operation::SwapRemove(position) => {
+                let (removed_index, _) = arena.swap_remove_index(position % len);
+                arena.release_idx(removed_index);
+            }
+            Operation::Swap(a, b) => arena.swap_index(a % len, b % len),
+            Operation::ApplyOrdering(keys) => {
+                let handles: Vec<Idx> = ordering(&keys, len)
+                    .into_iter()
+                    .filter_map(|position| arena.get_idx_at_index(position))
+                    .collect();
+                arena.apply_ordering(&handles);
+            }
+        }
+
+        None
+    }
+
+    /// Applies the operation to a plain `Vec`, for checking an arena against a model.
+    pub fn apply_to_vec(self, values: &mut Vec<T>) {
+        let len = values.len();
+
+        if let Operation::Alloc(value) = self {
+            values.push(value);
+            return;
+        }
+
+        if len == 0 {
+            return;
+        }
+
+        match self {
+            Operation::Alloc(_) => unreachable!(),
+            Operation::Remove(position) => {
+                values.remove(position % len);
+            }
+            Operation::SwapRemove(position) => {
+                values.swap_remove(position % len);
+            }
+            Operation::Swap(a, b) => values.swap(a % len, b % len),
+            Operation::ApplyOrdering(keys) => {
+                let mut old: Vec<Option<T>> = values.drain(..).map(Some).collect();
+                values.extend(
+                    ordering(&keys, len)
+                        .into_iter()
+                        .map(|position| old[position].take().unwrap()),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, T: arbitrary::Arbitrary<'a>> arbitrary::Arbitrary<'a> for Operation<T> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(match u.int_in_range(0..=4)? {
+            0 => Operation::Alloc(T::arbitrary(u)?),
+            1 => Operation::Remove(usize::arbitrary(u)?),
+            2 => Operation::SwapRemove(usize::arbitrary(u)?),
+            3 => Operation::Swap(usize::arbitrary(u)?, usize::arbitrary(u)?),
+            _ => Operation::ApplyOrdering(Vec::arbitrary(u)?),
+        })
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+impl<'a, T: arbitrary::Arbitrary<'a>> arbitrary::Arbitrary<'a> for Arena<T> {
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        Ok(Arena::from(Vec::<T>::arbitrary(u)?))
+    }
+}
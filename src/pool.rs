@@ -0,0 +1,63 @@
+use super::{Arena, Idx};
+use std::borrow::Borrow;
+
+/// Hands out handles that don't have values, for when only the identity of a handle matters.
+///
+/// This is an `Arena<()>` underneath, but frees in constant time since there's no order to keep,
+/// and freed handles are reused by later allocations once nothing refers to them.
+pub struct HandlePool {
+    arena: Arena<()>,
+}
+
+impl Default for HandlePool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HandlePool {
+    pub fn new() -> HandlePool {
+        Self {
+            arena: Arena::new(),
+        }
+    }
+
+    /// Creates a pool with `capacity` handles ready, so the first `capacity` allocations don't
+    /// allocate any memory. See [`Arena::with_full_capacity`].
+    pub fn with_capacity(capacity: usize) -> HandlePool {
+        Self {
+            arena: Arena::with_full_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.arena.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    pub fn alloc(&mut self) -> Idx {
+        self.arena.alloc(())
+    }
+
+    pub fn contains<I: Borrow<Idx>>(&self, index: I) -> bool {
+        self.arena.contains(index)
+    }
+
+    /// Frees a handle, marking it as removed. Returns `false` if it wasn't in this pool.
+    pub fn free<I: Borrow<Idx>>(&mut self, index: I) -> bool {
+        self.arena.try_swap_remove(index).is_ok()
+    }
+
+    /// Frees every handle, keeping them around to be reused.
+    pub fn clear(&mut self) {
+        self.arena.clear_reuse();
+    }
+
+    /// Iterates over the handles that haven't been freed, in no particular order.
+    pub fn iter(&self) -> impl Iterator<Item = &Idx> {
+        self.arena.entries_ref().map(|(idx, _)| idx)
+    }
+}
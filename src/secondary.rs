@@ -0,0 +1,79 @@
+use super::Idx;
+use std::borrow::Borrow;
+use std::collections::{hash_map, HashMap};
+
+/// Extra data for values in an [`Arena`](crate::Arena), stored outside of it and keyed by
+/// handle.
+///
+/// Entries aren't removed along with their values. Use
+/// [`Arena::prune_secondary_map`](crate::Arena::prune_secondary_map) to drop the entries for
+/// handles that are no longer in the arena.
+pub struct SecondaryMap<V> {
+    map: HashMap<Idx, V>,
+}
+
+impl<V> Default for SecondaryMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> SecondaryMap<V> {
+    pub fn new() -> SecondaryMap<V> {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    pub fn with_capacity(capacity: usize) -> SecondaryMap<V> {
+        Self {
+            map: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Sets the value for `index`, returning the previous one.
+    pub fn insert<I: Borrow<Idx>>(&mut self, index: I, value: V) -> Option<V> {
+        self.map.insert(index.borrow().clone(), value)
+    }
+
+    pub fn contains_key<I: Borrow<Idx>>(&self, index: I) -> bool {
+        self.map.contains_key(index.borrow())
+    }
+
+    pub fn get<I: Borrow<Idx>>(&self, index: I) -> Option<&V> {
+        self.map.get(index.borrow())
+    }
+
+    pub fn get_mut<I: Borrow<Idx>>(&mut self, index: I) -> Option<&mut V> {
+        self.map.get_mut(index.borrow())
+    }
+
+    pub fn remove<I: Borrow<Idx>>(&mut self, index: I) -> Option<V> {
+        self.map.remove(index.borrow())
+    }
+
+    pub fn retain<F: FnMut(&Idx, &mut V) -> bool>(&mut self, func: F) {
+        self.map.retain(func);
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    /// Iterates over the entries in no particular order.
+    pub fn iter(&self) -> hash_map::Iter<'_, Idx, V> {
+        self.map.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> hash_map::IterMut<'_, Idx, V> {
+        self.map.iter_mut()
+    }
+}
@@ -1,26 +1,185 @@
 use super::{Arena, Idx};
 use std::borrow::Borrow;
+use std::sync::Arc;
+
+/// The view returned by [`Arena::split_many`]. It's an [`ArenaSplit`] with several selected values.
+pub type ArenaSplitMany<'a, T> = ArenaSplit<'a, T>;
 
 pub struct ArenaSplit<'a, T> {
-    pub(crate) selected: Idx,
-    pub(crate) arena: &'a mut Arena<T>,
-    pub(crate) __type: std::marker::PhantomData<T>,
+    selected: Vec<Idx>,
+    arena: &'a mut Arena<T>,
+    // The address of the values' buffer when the split was made. The selected values are
+    // borrowed out of this buffer, so it must not move while the split exists. Kept as an
+    // address rather than a pointer so the split stays `Send` and `Sync`.
+    storage: usize,
+    __type: std::marker::PhantomData<T>,
+}
+
+impl<'a, T> ArenaSplit<'a, T> {
+    pub(crate) fn new(selected: Vec<Idx>, arena: &'a mut Arena<T>) -> ArenaSplit<'a, T> {
+        Self {
+            storage: arena.values.as_ptr() as usize,
+            selected,
+            arena,
+            __type: Default::default(),
+        }
+    }
 }
 
 impl<T> ArenaSplit<'_, T> {
+    fn debug_assert_storage_pinned(&self) {
+        debug_assert!(
+            self.storage == self.arena.values.as_ptr() as usize,
+            "The arena's storage moved while it was split!"
+        );
+    }
+
+    fn is_selected(&self, index: &Idx) -> bool {
+        self.selected.iter().any(|selected| selected == index)
+    }
+
     pub fn get<I: Borrow<Idx>>(&self, index: I) -> Option<&T> {
-        if index.borrow() == &self.selected {
+        let position = self.unselected_position(index.borrow())?;
+        // Safety: `position` is in bounds and isn't selected. Going through a raw pointer rather
+        // than indexing avoids borrowing the whole slice, which would cover the selected values.
+        Some(unsafe { &*self.arena.values.as_ptr().add(position) })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.entries().map(|(_, value)| value)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        // Go through a raw pointer rather than a slice, since a slice would cover the selected
+        // value that's already mutably borrowed.
+        let values = self.arena.values.as_mut_ptr();
+        let selected = &self.selected;
+        self.arena
+            .handles
+            .iter()
+            .enumerate()
+            .filter(move |(_, inner)| !selected.iter().any(|idx| Arc::ptr_eq(inner, &idx.inner)))
+            .map(move |(position, _)| unsafe { &mut *values.add(position) })
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (Idx, &T)> {
+        let values = self.arena.values.as_ptr();
+        let selected = &self.selected;
+        self.arena
+            .handles
+            .iter()
+            .enumerate()
+            .filter(move |(_, inner)| !selected.iter().any(|idx| Arc::ptr_eq(inner, &idx.inner)))
+            .map(move |(position, inner)| {
+                (
+                    Idx {
+                        inner: inner.clone(),
+                    },
+                    unsafe { &*values.add(position) },
+                )
+            })
+    }
+
+    /// Allocates a new value while the split is held.
+    ///
+    /// The selected value is borrowed straight out of the arena's storage, so the storage can't
+    /// grow while the split exists. Allocations have to fit in the capacity that was left over
+    /// when the split was made, see [`Arena::split_at_with_capacity`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the arena has no spare capacity.
+    pub fn alloc(&mut self, value: T) -> Idx {
+        self.alloc_with_idx(|_| value)
+    }
+
+    /// See [`ArenaSplit::alloc`].
+    pub fn alloc_with_idx<F: FnOnce(Idx) -> T>(&mut self, func: F) -> Idx {
+        assert!(
+            self.arena.values.len() < self.arena.values.capacity(),
+            "Trying to allocate in an ArenaSplit without spare capacity!"
+        );
+        let idx = self.arena.alloc_with_idx(func);
+        self.debug_assert_storage_pinned();
+        idx
+    }
+
+    pub fn get_mut<I: Borrow<Idx>>(&mut self, index: I) -> Option<&mut T> {
+        let position = self.unselected_position(index.borrow())?;
+        // Safety: see `ArenaSplit::get`
+        Some(unsafe { &mut *self.arena.values.as_mut_ptr().add(position) })
+    }
+
+    // The position of `index` if it's in the arena and not selected. Handles from another arena
+    // can have the same position as a selected value, so the position is checked against the
+    // arena's own handles rather than trusted.
+    fn unselected_position(&self, index: &Idx) -> Option<usize> {
+        if self.is_selected(index) {
             None
         } else {
-            self.arena.get(index)
+            self.arena.position_of(index)
         }
     }
 
-    pub fn get_mut<I: Borrow<Idx>>(&mut self, index: I) -> Option<&mut T> {
-        if index.borrow() == &self.selected {
-            None
+    /// Removes a value while the split is held, keeping the order of the remaining values.
+    ///
+    /// Returns `None` if `index` is selected or removed. Since the selected values can't move
+    /// while they're borrowed, this also returns `None` if a selected value comes after `index`.
+    pub fn remove<I: Borrow<Idx>>(&mut self, index: I) -> Option<T> {
+        let position = self.unselected_position(index.borrow())?;
+
+        if self
+            .selected
+            .iter()
+            .any(|selected| selected.value().is_some_and(|other| other > position))
+        {
+            return None;
+        }
+
+        let value = self.arena.remove_index(position);
+        self.debug_assert_storage_pinned();
+        Some(value)
+    }
+
+    /// Removes a value while the split is held by swapping the last value into its place.
+    ///
+    /// Returns `None` if `index` is selected or removed. Since the selected values can't move
+    /// while they're borrowed, this also returns `None` if the last value is selected.
+    pub fn swap_remove<I: Borrow<Idx>>(&mut self, index: I) -> Option<T> {
+        let position = self.unselected_position(index.borrow())?;
+        let last = self.arena.len() - 1;
+
+        if position != last
+            && self
+                .selected
+                .iter()
+                .any(|selected| selected.value() == Some(last))
+        {
+            return None;
+        }
+
+        let (removed_index, value) = self.arena.swap_remove_index(position);
+        self.arena.release_idx(removed_index);
+        self.debug_assert_storage_pinned();
+        Some(value)
+    }
+
+    pub fn split_at<I: Borrow<Idx>>(&mut self, selected: I) -> Option<(&mut T, ArenaSplit<'_, T>)> {
+        let selected = selected.borrow();
+
+        if let Some(value) = self.get_mut(selected) {
+            let value = value as *mut T;
+            let mut excluded = self.selected.clone();
+            excluded.push(selected.clone());
+
+            // Safety: see `Arena::split_at`. The nested split excludes everything this one does,
+            // plus `selected`.
+            Some((
+                unsafe { value.as_mut().unwrap() },
+                ArenaSplit::new(excluded, self.arena),
+            ))
         } else {
-            self.arena.get_mut(index)
+            None
         }
     }
 }
@@ -0,0 +1,87 @@
+use nano_arena::Arena;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Counts the allocations made on threads that have opted in, so the test harness's own
+// allocations on other threads don't get in the way.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    static COUNTING: Cell<bool> = const { Cell::new(false) };
+}
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if COUNTING.try_with(Cell::get).unwrap_or(false) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+fn count_allocations<R, F: FnOnce() -> R>(func: F) -> (R, usize) {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    COUNTING.with(|counting| counting.set(true));
+    let result = func();
+    COUNTING.with(|counting| counting.set(false));
+    (result, ALLOCATIONS.load(Ordering::Relaxed) - before)
+}
+
+#[test]
+fn full_capacity_allocs_without_allocating() {
+    let n = 1000;
+    let mut arena = Arena::with_full_capacity(n);
+    assert!(arena.capacity() >= n);
+
+    let (handles, allocations) = count_allocations(|| {
+        let mut handles = Vec::with_capacity(n);
+        for i in 0..n {
+            handles.push(arena.alloc(i));
+        }
+        handles
+    });
+
+    // The only allocation is the `Vec` holding the handles
+    assert_eq!(allocations, 1);
+    assert_eq!(arena.len(), n);
+    assert_eq!(arena.get(&handles[n - 1]), Some(&(n - 1)));
+
+    // Going past the reserved capacity allocates again
+    let (_, allocations) = count_allocations(|| arena.alloc(n));
+    assert!(allocations > 0);
+}
+
+#[test]
+fn with_capacity_allocates_a_handle_per_value() {
+    let n = 100;
+    let mut arena = Arena::with_capacity(n);
+
+    let (_, allocations) = count_allocations(|| {
+        for i in 0..n {
+            arena.alloc(i);
+        }
+    });
+
+    assert_eq!(allocations, n);
+}
+
+#[test]
+fn from_iter_allocates_storage_once() {
+    let n = 10_000;
+
+    let (arena, allocations) = count_allocations(|| (0..n).collect::<Arena<_>>());
+
+    // One allocation each for the values and the handles, and one for each handle's inner
+    assert_eq!(allocations, n + 2);
+    assert_eq!(arena.len(), n);
+}
@@ -0,0 +1,82 @@
+//! Run with `RUSTFLAGS="--cfg loom" cargo test --release --test loom`.
+#![cfg(loom)]
+
+use loom::sync::{Arc, RwLock};
+use loom::thread;
+use nano_arena::Arena;
+
+#[test]
+fn read_handles_while_removing() {
+    loom::model(|| {
+        let mut arena = Arena::new();
+        let first = arena.alloc(1);
+        let second = arena.alloc(2);
+
+        let removed = first.clone();
+        let writer = thread::spawn(move || {
+            assert_eq!(arena.remove(removed), 1);
+            arena
+        });
+
+        // The reader can see the arena before or after the removal, but never a handle that's
+        // both removed and pointing somewhere, or a position the value never had.
+        assert!(matches!(first.value(), Some(0) | None));
+        assert!(matches!(second.value(), Some(0) | Some(1)));
+        if first.is_removed() {
+            assert_eq!(second.value(), Some(0));
+        }
+
+        let arena = writer.join().unwrap();
+        assert!(first.is_removed());
+        assert_eq!(second.value(), Some(0));
+        assert_eq!(arena.get(&second), Some(&2));
+    });
+}
+
+#[test]
+fn snapshot_value_with_two_readers() {
+    loom::model(|| {
+        let mut arena = Arena::new();
+        let first = arena.alloc(1);
+        let _second = arena.alloc(2);
+        let last = arena.alloc(3);
+        let arena = Arc::new(RwLock::new(arena));
+
+        let writer = {
+            let (arena, first) = (arena.clone(), first.clone());
+            thread::spawn(move || {
+                assert_eq!(arena.write().unwrap().swap_remove(first), 1);
+            })
+        };
+
+        let readers: Vec<_> = (0..2)
+            .map(|_| {
+                let (arena, first, last) = (arena.clone(), first.clone(), last.clone());
+                thread::spawn(move || {
+                    // While the arena is borrowed, its snapshot is either entirely from before
+                    // the swap or entirely from after it, and matches what the handles say.
+                    let arena = arena.read().unwrap();
+                    let position = arena.snapshot_value(&last);
+
+                    match arena.snapshot_value(&first) {
+                        Some(0) => assert_eq!(position, Some(2)),
+                        None => assert_eq!(position, Some(0)),
+                        other => panic!("unexpected position {:?}", other),
+                    }
+
+                    assert_eq!(position, last.value());
+                    assert_eq!(arena.get(&last), Some(&3));
+                })
+            })
+            .collect();
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        writer.join().unwrap();
+
+        let arena = arena.read().unwrap();
+        assert_eq!(arena.snapshot_value(&first), None);
+        assert_eq!(arena.snapshot_value(&last), Some(0));
+    });
+}